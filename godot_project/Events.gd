@@ -4,7 +4,7 @@ signal mouse_event (event_type, grid_position, button_index)
 signal property_change (propname, value)
 signal exec_function (func_name, value)
 signal query_map_size ()
-signal return_map_size (num_chunks, chunk_size)
+signal return_map_size (num_chunks_x, num_chunks_z, chunk_size)
 
 func connect_signals (object:Node, signal_names:Array):
 	for signal_name in signal_names:
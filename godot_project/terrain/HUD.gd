@@ -33,6 +33,8 @@ var active_menu_button:MenuButton = null;
 var cursor_active:bool = true;
 var current_mode:int = 0;
 var current_file_path:String = "";
+var map_num_chunks_x:int = 1;
+var map_num_chunks_z:int = 1;
 
 
 func _ready():
@@ -117,22 +119,28 @@ func select_tool (index:int):
 		self.highlight_tool(self.current_mode);
 		Events.emit_signal("property_change", "draw_mode", index);
 
-func set_map_params (num_chunks:int, chunk_size:int):
-	$ResizePanel/CenterContainer2/VBoxContainer/VBoxContainer/NumChunks/NumChunksSpinBox.value = num_chunks;
+# The resize panel has one chunk count, for the X side. The Z side follows it so the map keeps its proportions
+func set_map_params (num_chunks_x:int, num_chunks_z:int, chunk_size:int):
+	self.map_num_chunks_x = num_chunks_x;
+	self.map_num_chunks_z = num_chunks_z;
+	$ResizePanel/CenterContainer2/VBoxContainer/VBoxContainer/NumChunks/NumChunksSpinBox.value = num_chunks_x;
 	$ResizePanel/CenterContainer2/VBoxContainer/VBoxContainer/ChunkSize/ChunkSizeSpinBox.value = chunk_size;
-	self.set_resize_label("before", num_chunks * chunk_size);
-	self.set_resize_label("after", num_chunks * chunk_size);
+	self.set_resize_label("before", num_chunks_x * chunk_size, num_chunks_z * chunk_size);
+	self.set_resize_label("after", num_chunks_x * chunk_size, num_chunks_z * chunk_size);
+
+func num_chunks_z_for (num_chunks_x:int) -> int:
+	return int(max(1, round(num_chunks_x * self.map_num_chunks_z / float(self.map_num_chunks_x))));
 
 func load_settings (_settings:Dictionary):
 	for key in _settings:
 		self.set_setting(key, _settings[key], true);
 
 
-func set_resize_label (name, map_size:int):
+func set_resize_label (name, map_width:int, map_depth:int):
 	if name == "before":
-		$ResizePanel/CenterContainer2/VBoxContainer/HBoxContainer2/BeforeSize.text = "%sx%s" % [map_size, map_size]; 	
+		$ResizePanel/CenterContainer2/VBoxContainer/HBoxContainer2/BeforeSize.text = "%sx%s" % [map_width, map_depth]; 	
 	elif name == "after":
-		$ResizePanel/CenterContainer2/VBoxContainer/HBoxContainer2/AfterSize.text = "%sx%s" % [map_size, map_size]; 	
+		$ResizePanel/CenterContainer2/VBoxContainer/HBoxContainer2/AfterSize.text = "%sx%s" % [map_width, map_depth]; 	
 
 func open_heightmap():
 	$Import.popup();
@@ -163,9 +171,7 @@ func export_mesh():
 func resize_canvas():
 	Events.call_deferred("emit_signal", "query_map_size");
 	var args = yield(Events, "return_map_size");
-	var num_chunks = args[0];
-	var chunk_size = args[1];
-	self.set_map_params(num_chunks, chunk_size);
+	self.set_map_params(args[0], args[1], args[2]);
 	$ResizePanel.visible = true;
 	Events.emit_signal("property_change", "paused", true);
 	$ResizePanel/CenterContainer2/VBoxContainer/HBoxContainer/ResizeButton.visible = false;
@@ -322,7 +328,7 @@ func _on_ResizeButton_button_up():
 	
 	var x = idx % 3;
 	var y = floor (idx / 3);
-	Events.emit_signal("exec_function", "resize_terrain", Rect2(chunk_size, num_chunks, x, y));
+	Events.emit_signal("exec_function", "resize_terrain", [chunk_size, num_chunks, self.num_chunks_z_for(num_chunks), x, y]);
 
 func _on_CancelResize_button_up():
 	Events.emit_signal("property_change", "paused", false);
@@ -344,12 +350,12 @@ func _on_StepHeightValue_value_changed(value):
 
 func _on_ChunkSizeSpinBox_value_changed(value):
 	var num_chunks:int = $ResizePanel/CenterContainer2/VBoxContainer/VBoxContainer/NumChunks/NumChunksSpinBox.value;
-	self.set_resize_label("after",num_chunks * value);
+	self.set_resize_label("after",num_chunks * value, self.num_chunks_z_for(num_chunks) * value);
 	$ResizePanel/CenterContainer2/VBoxContainer/HBoxContainer/ResizeButton.visible = true;
 	
 func _on_NumChunksSpinBox_value_changed(value):
 	var chunk_size:int = $ResizePanel/CenterContainer2/VBoxContainer/VBoxContainer/ChunkSize/ChunkSizeSpinBox.value;
-	self.set_resize_label("after",chunk_size * value);
+	self.set_resize_label("after",chunk_size * value, chunk_size * self.num_chunks_z_for(value));
 	$ResizePanel/CenterContainer2/VBoxContainer/HBoxContainer/ResizeButton.visible = true;
 	
 func _on_Undo_button_up():
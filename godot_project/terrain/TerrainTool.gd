@@ -16,7 +16,8 @@ var cursor_size:Vector2 = Vector2.ONE;
 var height_locked:bool = false;
 var prev_height:int = -1;
 
-export var num_chunks:int = 2;
+export var num_chunks_x:int = 2;
+export var num_chunks_z:int = 2;
 export var chunk_size:int = 8;
 
 var cursor_grid_position:Vector3;
@@ -42,7 +43,8 @@ onready var themes = [
 ]
 
 var draw_mode:int = DrawMode.ADD;
-export var map_size:int = 8;
+export var map_width:int = 8;
+export var map_depth:int = 8;
 
 export var init_terrain:bool = false setget _do_init_terrain;
 func _do_init_terrain(val:bool):
@@ -82,13 +84,13 @@ func update_cursor():
 	var grid_y:float = 0.0;
 	var height:int = self.height(int(self.cursor_grid_position.x), int(self.cursor_grid_position.z));
 	var is_height_locked:bool = self.is_height_locked();
-	var locked_height:int = self.locked_height();
+	var locked_height = self.locked_height();
 	
-	if !is_height_locked || (is_height_locked && locked_height == -1):
+	if !is_height_locked || (is_height_locked && locked_height == null):
 		if self.draw_mode == DrawMode.ERASE:
 			grid_y = height - self.cursor_size.y;
 		elif self.draw_mode == DrawMode.ADD:
-			if locked_height == -1:
+			if locked_height == null:
 				grid_y = height;
 			else:
 				grid_y = height - self.cursor_size.y;
@@ -108,7 +110,8 @@ func update_cursor():
 	self.cursor_grid_position.y = float(grid_y) * self.step_height;
 	$Cursor.global_transform.origin = self.cursor_grid_position + Vector3(-0.5, self.step_height / 2.0, -0.5);
 
-func locked_height () -> int:
+func locked_height ():
+	# null until a stroke starts
 	return self.terrain_data.call("get_locked_height");
 func is_height_locked () -> bool:
 	return self.terrain_data.call("get_is_height_locked");
@@ -124,12 +127,13 @@ func init():
 	self.init_chunks();
 
 func init_params ():
-	self.map_size = self.chunk_size * self.num_chunks;
-	self.terrain_data.call("init_params", self.num_chunks, self.chunk_size, self.step_height);
+	self.map_width = self.chunk_size * self.num_chunks_x;
+	self.map_depth = self.chunk_size * self.num_chunks_z;
+	self.terrain_data.call("init_params", self.num_chunks_x, self.num_chunks_z, self.chunk_size, self.step_height);
 	self.update_collision_shape();
 
 func init_chunks ():
-	$CanvasLayer/HUD.set_map_params(self.num_chunks, self.chunk_size);
+	$CanvasLayer/HUD.set_map_params(self.num_chunks_x, self.num_chunks_z, self.chunk_size);
 	for child in $Chunks.get_children():
 		child.queue_free();
 	get_tree().paused = true;
@@ -137,8 +141,8 @@ func init_chunks ():
 	yield(get_tree(), "idle_frame");
 	get_tree().paused = false;
 	var chunk_index = 0;
-	for z in range(self.num_chunks):
-		for x in range(self.num_chunks):
+	for z in range(self.num_chunks_z):
+		for x in range(self.num_chunks_x):
 			var chunk:MeshInstance = MeshInstance.new();
 			chunk.material_override = self.material_override;
 			chunk.translate(Vector3(x * self.chunk_size, 0, z * self.chunk_size));
@@ -152,11 +156,11 @@ func update_all_chunks():
 	self.update_vertex_count();
 
 func update_collision_shape():
-	$StaticBody.transform.origin.x = (self.chunk_size * self.num_chunks + 1) / 2;
-	$StaticBody.transform.origin.z = (self.chunk_size * self.num_chunks + 1) / 2;
+	$StaticBody.transform.origin.x = (self.chunk_size * self.num_chunks_x + 1) / 2;
+	$StaticBody.transform.origin.z = (self.chunk_size * self.num_chunks_z + 1) / 2;
 	var shape:HeightMapShape = $StaticBody/CollisionShape.shape;
-	shape.map_depth = self.chunk_size * self.num_chunks + 1;
-	shape.map_width = self.chunk_size * self.num_chunks + 1;	
+	shape.map_depth = self.chunk_size * self.num_chunks_z + 1;
+	shape.map_width = self.chunk_size * self.num_chunks_x + 1;	
 	shape.map_data = self.terrain_data.call("get_heights_for_collision");
 
 func save_file (path:String):
@@ -171,11 +175,19 @@ func open_file (path:String):
 		var variables:Dictionary = file.get_var(true);
 		file.close(); 
 		
-		if variables.has_all(["chunk_size", "num_chunks", "step_height", "heights"]):
+		# Files saved before maps could be rectangular only have num_chunks
+		if variables.has("num_chunks"):
+			variables['num_chunks_x'] = variables['num_chunks'];
+			variables['num_chunks_z'] = variables['num_chunks'];
+		
+		if variables.has_all(["chunk_size", "num_chunks_x", "num_chunks_z", "step_height", "heights"]):
 			self.chunk_size = int(variables['chunk_size']);
-			self.num_chunks = int(variables['num_chunks']);
+			self.num_chunks_x = int(variables['num_chunks_x']);
+			self.num_chunks_z = int(variables['num_chunks_z']);
 			self.step_height = float(variables['step_height']);
 			self.init_params();
+			if variables.has_all(["min_height", "max_height"]):
+				self.terrain_data.call("set_height_range", int(variables['min_height']), int(variables['max_height']));
 			self.terrain_data.call("set_heights", PoolIntArray(variables['heights']));
 			self.init_chunks();
 			self.update_collision_shape();
@@ -252,14 +264,17 @@ func _on_exec_function (func_name, value):
 			$CanvasLayer/HUD.update_undo_redo(self.terrain_data.call("can_undo"),self.terrain_data.call("can_redo"));
 			
 		"resize_terrain":
-			var new_chunk_size:int = (value as Rect2).position.x;
-			var new_num_chunks:int = (value as Rect2).position.y;
-			var x_amount:int = (value as Rect2).size.x;
-			var z_amount:int = (value as Rect2).size.y;
-			self.terrain_data.call("resize_terrain", new_chunk_size, new_num_chunks, x_amount, z_amount);
-			self.num_chunks = new_num_chunks;
+			var new_chunk_size:int = value[0];
+			var new_num_chunks_x:int = value[1];
+			var new_num_chunks_z:int = value[2];
+			var x_amount:int = value[3];
+			var z_amount:int = value[4];
+			self.terrain_data.call("resize_terrain", new_chunk_size, new_num_chunks_x, new_num_chunks_z, x_amount, z_amount);
+			self.num_chunks_x = new_num_chunks_x;
+			self.num_chunks_z = new_num_chunks_z;
 			self.chunk_size = new_chunk_size;
-			self.map_size = new_num_chunks * new_chunk_size;
+			self.map_width = new_num_chunks_x * new_chunk_size;
+			self.map_depth = new_num_chunks_z * new_chunk_size;
 			self.update_collision_shape();
 			self.init_chunks();
 		
@@ -271,7 +286,7 @@ func _on_exec_function (func_name, value):
 					self.load_theme();
 
 func _on_query_map_size ():
-	Events.emit_signal("return_map_size", self.num_chunks, self.chunk_size);
+	Events.emit_signal("return_map_size", self.num_chunks_x, self.num_chunks_z, self.chunk_size);
 
 func load_theme():
 	if self.env_theme != null:
@@ -0,0 +1,135 @@
+use super::structs::*;
+use gdnative::prelude::*;
+
+// Terrain measurements at one cell that paint rules are tested against
+#[derive(Clone,Copy,Debug)]
+pub struct CellShape {
+    pub height:f32,
+    pub slope:f32, // 0 on flat ground, approaching 1 on vertical walls
+    pub curvature:f32, // Positive in hollows and valleys, negative on ridges and peaks
+}
+
+impl CellShape {
+    // Measures a cell from its four neighbours, which are clamped to the map edge by the caller
+    pub fn from_neighbours (height:i32, left:i32, right:i32, up:i32, down:i32) -> Self {
+        let dx = (right - left) as f32 * 0.5;
+        let dz = (down - up) as f32 * 0.5;
+        let normal_y = 1.0 / (dx * dx + dz * dz + 1.0).sqrt();
+        CellShape {
+            height: height as f32,
+            slope: 1.0 - normal_y,
+            curvature: (left + right + up + down - 4 * height) as f32,
+        }
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct PaintRule {
+    pub min_height:f32,
+    pub max_height:f32,
+    pub min_slope:f32,
+    pub max_slope:f32,
+    pub min_curvature:f32,
+    pub max_curvature:f32,
+    pub noise:f32, // Shifts each cell's height and slope by up to this fraction of the rule's ranges
+    pub noise_scale:f32, // Size in cells of the noise features
+    pub color:Color8,
+    pub blend_mode:BlendMode,
+    pub strength:f32,
+}
+
+impl PaintRule {
+    pub fn new () -> Self {
+        PaintRule {
+            min_height: f32::MIN,
+            max_height: f32::MAX,
+            min_slope: 0.0,
+            max_slope: 1.0,
+            min_curvature: f32::MIN,
+            max_curvature: f32::MAX,
+            noise: 0.0,
+            noise_scale: 8.0,
+            color: Color8::new(255, 255, 255, 255),
+            blend_mode: BlendMode::Mix,
+            strength: 1.0,
+        }
+    }
+
+    // `seed` keeps the noise of different rules from lining up
+    pub fn matches (&self, shape:&CellShape, x:usize, z:usize, seed:u32) -> bool {
+        let mut height = shape.height;
+        let mut slope = shape.slope;
+        if self.noise > 0.0 {
+            let jitter = self.noise * value_noise(x as f32 / self.noise_scale, z as f32 / self.noise_scale, seed);
+            if self.min_height > f32::MIN && self.max_height < f32::MAX {
+                height += jitter * (self.max_height - self.min_height);
+            }
+            slope += jitter * (self.max_slope - self.min_slope);
+        }
+        return height >= self.min_height && height <= self.max_height
+            && slope >= self.min_slope && slope <= self.max_slope
+            && shape.curvature >= self.min_curvature && shape.curvature <= self.max_curvature;
+    }
+
+    pub fn to_dictionary (&self) -> Dictionary {
+        let dict = Dictionary::new();
+        dict.insert("min_height", self.min_height);
+        dict.insert("max_height", self.max_height);
+        dict.insert("min_slope", self.min_slope);
+        dict.insert("max_slope", self.max_slope);
+        dict.insert("min_curvature", self.min_curvature);
+        dict.insert("max_curvature", self.max_curvature);
+        dict.insert("noise", self.noise);
+        dict.insert("noise_scale", self.noise_scale);
+        dict.insert("color", self.color.to_color());
+        dict.insert("blend_mode", self.blend_mode as i64);
+        dict.insert("strength", self.strength);
+        return dict.into_shared();
+    }
+
+    // Missing keys keep their defaults, so a rule can be as simple as a color and a slope range
+    pub fn from_dictionary (dict:&Dictionary) -> Self {
+        let mut rule = PaintRule::new();
+        let get_f32 = |key:&str, default:f32| -> f32 {
+            return if dict.contains(key) { dict.get(key).to_f64() as f32 } else { default };
+        };
+        rule.min_height = get_f32("min_height", rule.min_height);
+        rule.max_height = get_f32("max_height", rule.max_height);
+        rule.min_slope = get_f32("min_slope", rule.min_slope);
+        rule.max_slope = get_f32("max_slope", rule.max_slope);
+        rule.min_curvature = get_f32("min_curvature", rule.min_curvature);
+        rule.max_curvature = get_f32("max_curvature", rule.max_curvature);
+        rule.noise = get_f32("noise", rule.noise).max(0.0);
+        rule.noise_scale = get_f32("noise_scale", rule.noise_scale).max(1.0);
+        rule.strength = get_f32("strength", rule.strength).clamp(0.0, 1.0);
+        if dict.contains("color") {
+            rule.color = Color8::from_color(dict.get("color").to_color());
+        }
+        if dict.contains("blend_mode") {
+            rule.blend_mode = BlendMode::from_i32(dict.get("blend_mode").to_i64() as i32);
+        }
+        return rule;
+    }
+}
+
+fn lattice_value (x:i32, z:i32, seed:u32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(374761393) ^ (z as u32).wrapping_mul(668265263) ^ seed.wrapping_mul(2246822519);
+    h = (h ^ (h >> 13)).wrapping_mul(1274126177);
+    h ^= h >> 16;
+    return (h & 0xffff) as f32 / 65535.0 * 2.0 - 1.0;
+}
+
+// Smooth value noise in [-1,1]
+pub fn value_noise (x:f32, z:f32, seed:u32) -> f32 {
+    let x0 = x.floor();
+    let z0 = z.floor();
+    let tx = x - x0;
+    let tz = z - z0;
+    let sx = tx * tx * (3.0 - 2.0 * tx);
+    let sz = tz * tz * (3.0 - 2.0 * tz);
+    let (x0, z0) = (x0 as i32, z0 as i32);
+
+    let top = lattice_value(x0, z0, seed) + (lattice_value(x0 + 1, z0, seed) - lattice_value(x0, z0, seed)) * sx;
+    let bottom = lattice_value(x0, z0 + 1, seed) + (lattice_value(x0 + 1, z0 + 1, seed) - lattice_value(x0, z0 + 1, seed)) * sx;
+    return top + (bottom - top) * sz;
+}
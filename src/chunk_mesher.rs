@@ -0,0 +1,189 @@
+use super::structs::*;
+use crate::marching_cubes::*;
+use crate::simplify::*;
+use crate::normals::*;
+use crate::uv::*;
+use crate::splat::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+use gdnative::prelude::{Color, Vector2, Vector3};
+use rayon::prelude::*;
+
+// Everything a chunk's mesh is built from. It borrows the terrain's data to mesh in place, or owns a copy of it
+// so a rebuild can carry on in the background while the terrain keeps changing
+#[derive(Clone)]
+pub struct ChunkMesher<'a> {
+    pub heights:Cow<'a, Vec<i32>>,
+    pub vertex_colors:Cow<'a, Vec<usize>>,
+    pub color_list:Cow<'a, Vec<Color8>>,
+    pub splat_channels:usize,
+    pub splat_weights:Cow<'a, Vec<SplatWeights>>,
+    pub chunk_lod:Cow<'a, Vec<usize>>,
+    pub map_width:usize,
+    pub map_depth:usize,
+    pub chunk_size:usize,
+    pub num_chunks_x:usize,
+    pub num_chunks_z:usize,
+    pub simplify_flat:bool,
+    pub border_walls:bool,
+    pub border_cap:bool,
+    pub base_height:f32,
+    pub crease_angle:f32,
+    pub generate_uvs:bool,
+    pub uv_scale:f32,
+}
+
+// A chunk's final vertex arrays, ready to be handed to a SurfaceTool. The UV arrays are empty without generated UVs
+pub struct ChunkSurface {
+    pub vertices:Vec<Vector3>,
+    pub normals:Vec<Vector3>,
+    pub colors:Vec<Color>,
+    pub uvs:Vec<Vector2>,
+    pub uv2s:Vec<Vector2>,
+    pub indices:Vec<usize>,
+}
+
+// What a rebuild sends back for each chunk: the arrays for the chunk's collision and export data, and its surface.
+// `version` identifies the data it was built from, so results that arrive too late can be told apart
+pub struct ChunkResult {
+    pub chunk_id:usize,
+    pub version:u64,
+    pub vertices:Vec<Vector3>,
+    pub indices:Vec<usize>,
+    pub surface:ChunkSurface,
+}
+
+impl<'a> ChunkMesher<'a> {
+    pub fn into_owned (self) -> ChunkMesher<'static> {
+        ChunkMesher {
+            heights: Cow::Owned(self.heights.into_owned()),
+            vertex_colors: Cow::Owned(self.vertex_colors.into_owned()),
+            color_list: Cow::Owned(self.color_list.into_owned()),
+            splat_channels: self.splat_channels,
+            splat_weights: Cow::Owned(self.splat_weights.into_owned()),
+            chunk_lod: Cow::Owned(self.chunk_lod.into_owned()),
+            map_width: self.map_width,
+            map_depth: self.map_depth,
+            chunk_size: self.chunk_size,
+            num_chunks_x: self.num_chunks_x,
+            num_chunks_z: self.num_chunks_z,
+            simplify_flat: self.simplify_flat,
+            border_walls: self.border_walls,
+            border_cap: self.border_cap,
+            base_height: self.base_height,
+            crease_angle: self.crease_angle,
+            generate_uvs: self.generate_uvs,
+            uv_scale: self.uv_scale,
+        }
+    }
+
+    fn chunk_offset (&self, chunk_id:usize) -> (i32, i32) {
+        return ((self.chunk_size * (chunk_id % self.num_chunks_x)) as i32, (self.chunk_size * (chunk_id / self.num_chunks_x)) as i32);
+    }
+
+    fn cell_index (&self, x:i32, z:i32) -> usize {
+        let x = x.max(0).min(self.map_width as i32 - 1) as usize;
+        let z = z.max(0).min(self.map_depth as i32 - 1) as usize;
+        return z * self.map_width + x;
+    }
+
+    // An id per vertex that is equal only where the surface looks the same, for merging and decimating.
+    // That is the palette index, or an id per distinct set of weights in splat mode
+    pub fn material_ids (&self, vertices:&Vec<Vector3>, offset_x:i32, offset_z:i32) -> Vec<usize> {
+        if self.splat_channels == 0 {
+            return vertices.iter().map(|vert| self.vertex_colors[self.cell_index(vert.x.floor() as i32 + offset_x, vert.z.floor() as i32 + offset_z)]).collect();
+        }
+        let mut ids:HashMap<SplatWeights,usize> = HashMap::new();
+        return vertices.iter().map(|vert| {
+            let weights = self.splat_weights[self.cell_index(vert.x.floor() as i32 + offset_x, vert.z.floor() as i32 + offset_z)];
+            let next_id = ids.len();
+            *ids.entry(weights).or_insert(next_id)
+        }).collect();
+    }
+
+    // Marches the chunk and indexes its vertices, in chunk space
+    pub fn build_chunk (&self, chunk_id:usize) -> (Vec<Vector3>, Vec<usize>) {
+        let mut soup:Vec<Vector3> = generate_chunk_mesh_from_heightmap(&self.heights, chunk_id, self.chunk_size, self.num_chunks_x, self.num_chunks_z, &self.chunk_lod);
+        if self.border_walls || self.border_cap {
+            let faces = border_faces(chunk_id, self.num_chunks_x, self.num_chunks_z);
+            add_border_walls(&mut soup, faces, self.chunk_size, self.base_height, self.border_walls, self.border_cap);
+        }
+
+        let mut vertices:Vec<Vector3> = Vec::new();
+        let mut indices:Vec<usize> = Vec::with_capacity(soup.len());
+        let mut vertex_map:HashMap<Vector3Key,usize> = HashMap::new();
+        for point in soup.iter() {
+            let index = *vertex_map.entry(Vector3Key::from_vector3(*point)).or_insert_with(|| {
+                vertices.push(*point);
+                vertices.len() - 1
+            });
+            indices.push(index);
+        }
+
+        if self.simplify_flat {
+            let (offset_x, offset_z) = self.chunk_offset(chunk_id);
+            let colors = self.material_ids(&vertices, offset_x, offset_z);
+            return merge_flat_quads(&vertices, &indices, &colors, self.chunk_size);
+        }
+        return (vertices, indices);
+    }
+
+    // Shades a chunk built by build_chunk
+    pub fn build_surface (&self, chunk_id:usize, vertices:&Vec<Vector3>, indices:&Vec<usize>) -> ChunkSurface {
+        let (offset_x, offset_z) = self.chunk_offset(chunk_id);
+        let (vertices, normals, indices) = crease_normals(vertices, indices, self.crease_angle);
+        let (uvs, uv2s) = if self.generate_uvs {
+            generate_uvs(&vertices, &normals, Vector3::new(offset_x as f32, 0.0, offset_z as f32), self.uv_scale)
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        // Godot 3 meshes have no custom channels, so splat mode packs the first four weights into the color.
+        // Materials past the fourth are read from the splat texture
+        let colors:Vec<Color> = vertices.iter().map(|vert| {
+            let index = self.cell_index(vert.x.floor() as i32 + offset_x, vert.z.floor() as i32 + offset_z);
+            if self.splat_channels > 0 {
+                pack_layer(&self.splat_weights[index], 0)
+            } else {
+                self.color_list[self.vertex_colors[index]].to_color()
+            }
+        }).collect();
+        return ChunkSurface {
+            vertices: vertices,
+            normals: normals,
+            colors: colors,
+            uvs: uvs,
+            uv2s: uv2s,
+            indices: indices,
+        };
+    }
+
+    pub fn build (&self, chunk_id:usize) -> ChunkResult {
+        let (vertices, indices) = self.build_chunk(chunk_id);
+        let surface = self.build_surface(chunk_id, &vertices, &indices);
+        return ChunkResult {
+            chunk_id: chunk_id,
+            version: 0,
+            vertices: vertices,
+            indices: indices,
+            surface: surface,
+        };
+    }
+
+    // Builds the chunks on the worker pool and waits for all of them
+    pub fn build_all (&self, chunk_ids:&Vec<usize>) -> Vec<ChunkResult> {
+        return chunk_ids.par_iter().map(|chunk_id| self.build(*chunk_id)).collect();
+    }
+}
+
+// Starts building the chunks, each paired with the version of the data being meshed, on the worker pool and returns
+// straight away. Each chunk is sent back as soon as it is done. Once the receiver is gone the remaining chunks are skipped
+pub fn spawn_rebuild (mesher:ChunkMesher<'static>, chunks:Vec<(usize,u64)>, sender:Sender<ChunkResult>) {
+    rayon::spawn(move || {
+        let _ = chunks.par_iter().try_for_each_with(sender, |sender, (chunk_id, version)| {
+            let mut result = mesher.build(*chunk_id);
+            result.version = *version;
+            sender.send(result)
+        });
+    });
+}
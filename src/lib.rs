@@ -3,6 +3,14 @@ use gdnative::prelude::*;
 mod tables;
 mod structs;
 mod marching_cubes;
+mod simplify;
+mod normals;
+mod uv;
+mod spline;
+mod selection;
+mod auto_paint;
+mod splat;
+mod chunk_mesher;
 mod terrain_utils;
 mod terrain;
 
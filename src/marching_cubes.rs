@@ -1,6 +1,7 @@
 use super::tables::*;
 use super::structs::*;
 use gdnative::prelude::Vector3;
+use std::collections::HashMap;
 
 const EMPTY_TRI:Triangle = Triangle::new(
     Vector3::new(0.0, 0.0, 0.0),
@@ -16,49 +17,155 @@ const ADJACENT:[(u8,u8);3] = [
     (1, 1)
 ];
 
-pub fn generate_chunk_mesh_from_heightmap (heights:&Vec<i32>, chunk_id:usize, chunk_size:usize, num_chunks:usize, lod_list:&Vec<usize>) -> Vec<Vector3> {
+// Chunk faces as the (column, row) offset of the chunk on the other side
+const CHUNK_FACES:[(i32,i32);4] = [
+    (-1, 0),
+    (1, 0),
+    (0, -1),
+    (0, 1)
+];
+
+// Whether each face's transition cells have to be wound the other way round to face the same side as the chunk's cells
+const FLIP_TRANSITION:[bool;4] = [false, true, true, false];
+
+// Full resolution samples in the order of the bits of a transition cell case
+const TRANSITION_CASE_ORDER:[usize;9] = [0, 1, 2, 5, 8, 7, 6, 3, 4];
+
+// Triangles of a single cell with a vertex halfway along each edge the surface crosses. Bit i of `densities` is set when POINTS[i] is solid
+pub fn march (densities:u8) -> [Triangle; 5] {
+    let mut triangles:[Triangle; 5] = [EMPTY_TRI; 5];
+    let edges = &TRI_TABLE[densities as usize];
+    let edge_point = |edge:i8| -> Vector3 {
+        let a = POINTS[EDGE_TO_POINTS[edge as usize * 2] as usize];
+        let b = POINTS[EDGE_TO_POINTS[edge as usize * 2 + 1] as usize];
+        return (a + b) * 0.5;
+    };
+    for i in 0..5 {
+        if edges[i * 3] == -1 {
+            break;
+        }
+        triangles[i] = Triangle::new(edge_point(edges[i * 3]), edge_point(edges[i * 3 + 2]), edge_point(edges[i * 3 + 1]));
+    }
+    return triangles;
+}
+
+// Transvoxel transition cell (Lengyel 2010) joining nine full resolution samples on a chunk face to the four corners of a half resolution cell
+// `samples` are true where solid and `points` holds the position of each of the thirteen TRANSITION_POINTS
+fn transition_cell (samples:&[bool;9], points:&[Vector3;13], flip:bool, verts_out:&mut Vec<Vector3>) {
+    let mut case:usize = 0;
+    for (bit, sample) in TRANSITION_CASE_ORDER.iter().enumerate() {
+        if samples[*sample] {
+            case |= 1 << bit;
+        }
+    }
+    let class = TRANSITION_EDGE_TABLE[case];
+    let indices = &TRANSITION_TRI_DATA[(class & 0x7F) as usize];
+    let vertex_data = &TRANSITION_VERTEX_DATA[case];
+    let invert = (class & 0x80 != 0) != flip;
+
+    let vertex = |index:i8| -> Vector3 {
+        let edge = vertex_data[index as usize];
+        let a = ((edge >> 4) & 0x0F) as usize;
+        let b = (edge & 0x0F) as usize;
+        return (points[a] + points[b]) * 0.5;
+    };
+
+    for i in (0..indices.len()).step_by(3) {
+        if indices[i] == -1 {
+            break;
+        }
+        let a = vertex(indices[i]);
+        let (b, c) = if invert {
+            (vertex(indices[i + 2]), vertex(indices[i + 1]))
+        } else {
+            (vertex(indices[i + 1]), vertex(indices[i + 2]))
+        };
+        // Cells are flattened onto the face, so some triangles collapse
+        if a == b || b == c || a == c {
+            continue;
+        }
+        verts_out.push(a);
+        verts_out.push(b);
+        verts_out.push(c);
+    }
+}
+
+// Lowers LODs until no chunk is more than one level coarser than a neighbour, which is all a transition cell can stitch
+pub fn limit_lod_steps (lod_list:&mut Vec<usize>, num_chunks_x:usize, num_chunks_z:usize) {
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for chunk_id in 0..lod_list.len() {
+            let col = chunk_id % num_chunks_x;
+            let row = chunk_id / num_chunks_x;
+            let mut limit = lod_list[chunk_id];
+            if col > 0 {
+                limit = limit.min(lod_list[chunk_id - 1] + 1);
+            }
+            if col < num_chunks_x - 1 {
+                limit = limit.min(lod_list[chunk_id + 1] + 1);
+            }
+            if row > 0 {
+                limit = limit.min(lod_list[chunk_id - num_chunks_x] + 1);
+            }
+            if row < num_chunks_z - 1 {
+                limit = limit.min(lod_list[chunk_id + num_chunks_x] + 1);
+            }
+            if limit < lod_list[chunk_id] {
+                lod_list[chunk_id] = limit;
+                changed = true;
+            }
+        }
+    }
+}
+
+// Highest LOD whose cells still fit in a chunk, i.e. the largest n with 2^n <= chunk_size
+pub fn clamp_lod (lod:usize, chunk_size:usize) -> usize {
+    let max_lod = (usize::BITS - 1 - chunk_size.max(1).leading_zeros()) as usize;
+    return lod.min(max_lod);
+}
+
+// Chunks at LOD n march cells 2^n wide and one unit tall, so vertical detail is kept at every LOD
+pub fn generate_chunk_mesh_from_heightmap (heights:&Vec<i32>, chunk_id:usize, chunk_size:usize, num_chunks_x:usize, num_chunks_z:usize, lod_list:&Vec<usize>) -> Vec<Vector3> {
     let mut verts_out:Vec<Vector3> = Vec::new();
     
-    let chunk_col:usize = chunk_id % num_chunks;
-    let chunk_row:usize = chunk_id / num_chunks;
+    let chunk_col:usize = chunk_id % num_chunks_x;
+    let chunk_row:usize = chunk_id / num_chunks_x;
     let offset_x = chunk_col * chunk_size;
     let offset_z = chunk_row * chunk_size;
-    let map_size = chunk_size * num_chunks;
-
-    let transition_top = chunk_row > 0 && lod_list[(chunk_row - 1) * num_chunks as usize + chunk_col] > lod_list[chunk_id];
-    let transition_bottom = chunk_row < num_chunks as usize - 1 && lod_list[(chunk_row + 1) * num_chunks as usize + chunk_col] > lod_list[chunk_id];
-
-    let transition_left = chunk_col > 0 && lod_list[chunk_row * num_chunks as usize + chunk_col - 1] >  lod_list[chunk_id];
-    let transition_right = chunk_col < num_chunks as usize - 1 && lod_list[chunk_row * num_chunks as usize + chunk_col + 1] > lod_list[chunk_id];
+    let map_width = chunk_size * num_chunks_x;
+    let map_depth = chunk_size * num_chunks_z;
+
+    let lod = clamp_lod(lod_list[chunk_id], chunk_size);
+    let step:usize = 1 << lod;
+    // When the chunk size isn't a multiple of the step the last cell in each direction is narrower
+    let num_cells = (chunk_size + step - 1) / step;
+    let cell_width = |cell:usize| -> usize {
+        return step.min(chunk_size - cell * step);
+    };
 
-    let get_height_range = |current_height:i32, x:usize, z:usize| -> (i32, i32) {
-        let mut max_height:i32 = current_height;
-        let mut min_height:i32 = current_height;
-        for dir in ADJACENT {
-            let x_min:usize = (x + dir.0 as usize).min(chunk_size * num_chunks - 1);
-            let z_min:usize = (z + dir.1 as usize).min(chunk_size * num_chunks - 1);
-            let adjacent_height = heights[z_min * map_size + x_min];
-            max_height = max_height.max(adjacent_height);
-            min_height = min_height.min(adjacent_height);
-        }
-        return (min_height,max_height);
+    let height_at = |x:usize, z:usize| -> i32 {
+        return heights[z.min(map_depth - 1) * map_width + x.min(map_width - 1)];
     };
 
-    for x in 0..chunk_size {
-        for z in 0..chunk_size {
-            let global_x = x + offset_x;
-            let global_z = z + offset_z;
-            let current_height = heights[global_z * map_size + global_x];
-            let height_range = get_height_range(current_height, global_x, global_z);
+    for cell_x in 0..num_cells {
+        for cell_z in 0..num_cells {
+            let x = cell_x * step;
+            let z = cell_z * step;
+            let width_x = cell_width(cell_x);
+            let width_z = cell_width(cell_z);
+            let mut corner_heights:[i32; 8] = [0; 8];
+            for i in 0..8 as usize {
+                corner_heights[i] = height_at(offset_x + x + POINTS[i].x as usize * width_x, offset_z + z + POINTS[i].z as usize * width_z);
+            }
+            let min_height = *corner_heights.iter().min().unwrap();
+            let max_height = *corner_heights.iter().max().unwrap();
             
-            for y in height_range.0..=height_range.1 {
+            for y in min_height..=max_height {
                 let mut densities:u8 = 0b00000000;
 
                 for i in 0..8 as usize {
-                    let x_min:usize = (global_x + POINTS[i].x as usize).min(chunk_size * num_chunks - 1);
-                    let z_min:usize = (global_z + POINTS[i].z as usize).min(chunk_size * num_chunks - 1);
-                    let height:i32 = heights[z_min * map_size + x_min];
-                    if y + POINTS[i].y as i32 <= height {
+                    if y + POINTS[i].y as i32 <= corner_heights[i] {
                         densities |= 1 << i;
                     }
                 }
@@ -68,36 +175,90 @@ pub fn generate_chunk_mesh_from_heightmap (heights:&Vec<i32>, chunk_id:usize, ch
                     if *triangle == EMPTY_TRI {
                         break;
                     }
-                    else {
-                        for point in triangle.points.iter() {
-                            let mut vert = *point + Vector3::new(x as f32, y as f32, z as f32);
-                            
-                            let t_top = height_range.1 != height_range.0 && transition_top && vert.z as i32 == 0 && vert.x != vert.x.trunc();
-                            let t_bottom = height_range.1 != height_range.0 && transition_bottom && vert.z as i32 == chunk_size as i32 && vert.x != vert.x.trunc();
-                            let t_left = height_range.1 != height_range.0 && transition_left && vert.x as i32 == 0 && vert.z != vert.z.trunc();
-                            let t_right = height_range.1 != height_range.0 && transition_right && vert.x as i32 == chunk_size as i32 && vert.z != vert.z.trunc();
-                            
-                            if t_top || t_bottom || t_left || t_right {
-                                vert.y = 0.5 * height_range.0 as f32 + 0.5 * height_range.1 as f32 + 0.5;
-                            }
-
-
-                            verts_out.push(vert);
-                        }
+                    for point in triangle.points.iter() {
+                        verts_out.push(Vector3::new(
+                            point.x * width_x as f32 + x as f32,
+                            point.y + y as f32,
+                            point.z * width_z as f32 + z as f32
+                        ));
                     }
                 }
             }
-            
+        }
+    }
+
+    if lod == 0 {
+        return verts_out;
+    }
+
+    // Faces next to a finer chunk are stitched to it with transition cells. They are flattened onto the face so the
+    // cells above never have to be moved aside, and assume the finer chunk is exactly one LOD below
+    let half_step = step / 2;
+    for face in 0..CHUNK_FACES.len() {
+        let neighbour_col = chunk_col as i32 + CHUNK_FACES[face].0;
+        let neighbour_row = chunk_row as i32 + CHUNK_FACES[face].1;
+        if neighbour_col < 0 || neighbour_row < 0 || neighbour_col >= num_chunks_x as i32 || neighbour_row >= num_chunks_z as i32 {
+            continue;
+        }
+        if clamp_lod(lod_list[neighbour_row as usize * num_chunks_x + neighbour_col as usize], chunk_size) >= lod {
+            continue;
+        }
+
+        // Chunk position of a point `u` along the face
+        let face_point = |u:f32, y:f32| -> Vector3 {
+            return match face {
+                0 => Vector3::new(0.0, y, u),
+                1 => Vector3::new(chunk_size as f32, y, u),
+                2 => Vector3::new(u, y, 0.0),
+                _ => Vector3::new(u, y, chunk_size as f32),
+            };
+        };
+        let face_height = |u:usize| -> i32 {
+            return match face {
+                0 => height_at(offset_x, offset_z + u),
+                1 => height_at(offset_x + chunk_size, offset_z + u),
+                2 => height_at(offset_x + u, offset_z),
+                _ => height_at(offset_x + u, offset_z + chunk_size),
+            };
+        };
+
+        for cell in 0..num_cells {
+            let u = cell * step;
+            let width = cell_width(cell);
+            // A last cell no wider than the finer chunk's cells already shares its edges
+            if width <= half_step {
+                continue;
+            }
+            let column_heights:[i32; 3] = [face_height(u), face_height(u + half_step), face_height(u + width)];
+            let min_height = *column_heights.iter().min().unwrap();
+            let max_height = *column_heights.iter().max().unwrap();
+
+            for y in min_height..=max_height {
+                // Cells are one unit tall at every LOD, so the middle row of samples matches the top row and is moved onto it
+                let mut samples:[bool; 9] = [false; 9];
+                for i in 0..9 {
+                    let sample_y = if i < 3 { y } else { y + 1 };
+                    samples[i] = sample_y <= column_heights[i % 3];
+                }
+                let mut points:[Vector3; 13] = [Vector3::new(0.0, 0.0, 0.0); 13];
+                for i in 0..13 {
+                    let point = TRANSITION_POINTS[i];
+                    points[i] = face_point(u as f32 + (point.x * step as f32).min(width as f32), y as f32 + point.z.ceil());
+                }
+                transition_cell(&samples, &points, FLIP_TRANSITION[face], &mut verts_out);
+            }
         }
     }
     return verts_out;
 }
 
-pub fn generate_chunk_mesh_from_height_array (heights:&Vec<i32>, chunk_id:usize, chunk_size:usize, num_chunks:usize) -> Vec<Vector3> {
+pub fn generate_chunk_mesh_from_height_array (heights:&Vec<i32>, chunk_id:usize, chunk_size:usize, num_chunks_x:usize, num_chunks_z:usize) -> Vec<Vector3> {
     let mut verts_out:Vec<Vector3> = Vec::new();
     
-    let chunk_col:usize = chunk_id % num_chunks;
-    let chunk_row:usize = chunk_id / num_chunks;
+    let chunk_col:usize = chunk_id % num_chunks_x;
+    let chunk_row:usize = chunk_id / num_chunks_x;
+    let map_width = chunk_size * num_chunks_x;
+    let map_depth = chunk_size * num_chunks_z;
     let offset_x = chunk_col * chunk_size;
     let offset_z = chunk_row * chunk_size;
 
@@ -105,14 +266,14 @@ pub fn generate_chunk_mesh_from_height_array (heights:&Vec<i32>, chunk_id:usize,
         for z in 0..chunk_size {
             let global_x = x + offset_x;
             let global_z = z + offset_z;
-            let current_height = heights[global_z * chunk_size * num_chunks + global_x] as usize;
+            let current_height = heights[global_z * map_width + global_x];
             let mut max_height = current_height;
             let mut min_height = current_height;
 
             for dir in ADJACENT {
-                let x_min:usize = (global_x + dir.0 as usize).min(chunk_size * num_chunks - 1);
-                let z_min:usize = (global_z + dir.1 as usize).min(chunk_size * num_chunks - 1);
-                let adjacent_height = heights[z_min * chunk_size * num_chunks + x_min] as usize;
+                let x_min:usize = (global_x + dir.0 as usize).min(map_width - 1);
+                let z_min:usize = (global_z + dir.1 as usize).min(map_depth - 1);
+                let adjacent_height = heights[z_min * map_width + x_min];
                 max_height = max_height.max(adjacent_height);
                 min_height = min_height.min(adjacent_height);
             }
@@ -121,10 +282,10 @@ pub fn generate_chunk_mesh_from_height_array (heights:&Vec<i32>, chunk_id:usize,
                 let mut densities:u8 = 0b00000000;
 
                 for i in 0..8 as usize {
-                    let x_min:usize = (global_x + POINTS[i].x as usize).min(chunk_size * num_chunks - 1);
-                    let z_min:usize = (global_z + POINTS[i].z as usize).min(chunk_size * num_chunks - 1);
-                    let height:i32 = heights[z_min * chunk_size * num_chunks + x_min];
-                    if y + POINTS[i].y as usize <= height as usize {
+                    let x_min:usize = (global_x + POINTS[i].x as usize).min(map_width - 1);
+                    let z_min:usize = (global_z + POINTS[i].z as usize).min(map_depth - 1);
+                    let height:i32 = heights[z_min * map_width + x_min];
+                    if y + POINTS[i].y as i32 <= height {
                         densities |= 1 << i;
                     }
                 }
@@ -147,105 +308,185 @@ pub fn generate_chunk_mesh_from_height_array (heights:&Vec<i32>, chunk_id:usize,
     return verts_out;
 }
 
+// Which faces of a chunk lie on the outer border of the map, in CHUNK_FACES order
+pub fn border_faces (chunk_id:usize, num_chunks_x:usize, num_chunks_z:usize) -> [bool; 4] {
+    let chunk_col = chunk_id % num_chunks_x;
+    let chunk_row = chunk_id / num_chunks_x;
+    return [chunk_col == 0, chunk_col == num_chunks_x - 1, chunk_row == 0, chunk_row == num_chunks_z - 1];
+}
 
-pub fn generate_chunk_lod_mesh (heights:&Vec<i32>, chunk_id:usize, chunk_size:usize, num_chunks:usize, lod_list:&Vec<usize>, indices_out:&mut Vec<usize>, vertices_out:&mut Vec<Vector3>) {
-    let mut verts_out:Vec<Vector3> = Vec::new();
-
-    let chunk_col:usize = chunk_id % num_chunks;
-    let chunk_row:usize = chunk_id / num_chunks;
-    let offset_x = chunk_col * chunk_size;
-    let offset_z = chunk_row * chunk_size;
-
-    let transition_top = chunk_row > 0 && lod_list[(chunk_row - 1) * num_chunks as usize + chunk_col] > lod_list[chunk_id];
-    let transition_bottom = chunk_row < num_chunks as usize - 1 && lod_list[(chunk_row + 1) * num_chunks as usize + chunk_col] > lod_list[chunk_id];
-
-    let transition_left = chunk_col > 0 && lod_list[chunk_row * num_chunks as usize + chunk_col - 1] >  lod_list[chunk_id];
-    let transition_right = chunk_col < num_chunks as usize - 1 && lod_list[chunk_row * num_chunks as usize + chunk_col + 1] > lod_list[chunk_id];
-
-
-    let lod = lod_list[chunk_id] - 1;
-    let step = 1 << lod;
-    let lod_chunk_size = chunk_size / step;
-    let map_size = chunk_size * num_chunks;
+// Closes a chunk mesh where it meets the border of the map. With `walls`, every open edge on a border face is extended
+// straight down to base_height, and with `cap` the chunk gets a floor at that height, so the map reads as a solid block.
+// base_height has to be below the surface, otherwise the walls turn inside out
+pub fn add_border_walls (verts_out:&mut Vec<Vector3>, faces:[bool; 4], chunk_size:usize, base_height:f32, walls:bool, cap:bool) {
+    let size = chunk_size as f32;
+    let on_face = |point:Vector3, face:usize| -> bool {
+        return match face {
+            0 => point.x == 0.0,
+            1 => point.x == size,
+            2 => point.z == 0.0,
+            _ => point.z == size,
+        };
+    };
+    // Position along a face, running the same way as the floor's outline
+    let along_face = |point:Vector3, face:usize| -> f32 {
+        return match face {
+            0 => size - point.z,
+            1 => point.z,
+            2 => point.x,
+            _ => size - point.x,
+        };
+    };
+    let key = |point:Vector3| -> (i32,i32,i32) {
+        return ((point.x * 4.0).round() as i32, (point.y * 4.0).round() as i32, (point.z * 4.0).round() as i32);
+    };
 
-    for z in 0..(lod_chunk_size + 1) {
-        for x in 0..(lod_chunk_size + 1) {
-            let global_x = (x * step + offset_x).min(map_size - 1);
-            let global_z = (z * step + offset_z).min(map_size - 1);
-            let current_height = heights[global_z * map_size + global_x];
+    let mut face_points:Vec<Vec<f32>> = vec![vec![0.0, size]; 4];
+    if walls {
+        let mut edge_counts:HashMap<((i32,i32,i32),(i32,i32,i32)),usize> = HashMap::new();
+        let mut border_edges:Vec<(Vector3,Vector3,usize)> = Vec::new();
+        for triangle in 0..verts_out.len() / 3 {
+            for corner in 0..3 {
+                let a = verts_out[triangle * 3 + corner];
+                let b = verts_out[triangle * 3 + (corner + 1) % 3];
+                let (key_a, key_b) = (key(a), key(b));
+                let edge_key = if key_a < key_b { (key_a, key_b) } else { (key_b, key_a) };
+                *edge_counts.entry(edge_key).or_insert(0) += 1;
+                if let Some(face) = (0..4).find(|face| faces[*face] && on_face(a, *face) && on_face(b, *face)) {
+                    border_edges.push((a, b, face));
+                }
+            }
+        }
 
-            let new_vertex:Vector3 = Vector3::new(
-                (x * step) as f32,
-                current_height as f32 + 0.5, // Add 0.5 because marching cubes adds faces between cells, not on cell borders
-                (z * step) as f32
-            );
-            vertices_out.push(new_vertex);
+        for (a, b, face) in border_edges.iter().cloned() {
+            let (key_a, key_b) = (key(a), key(b));
+            let edge_key = if key_a < key_b { (key_a, key_b) } else { (key_b, key_a) };
+            if edge_counts[&edge_key] != 1 {
+                continue;
+            }
+            // The wall shares the edge with the surface, so it runs the other way round
+            let a_base = Vector3::new(a.x, base_height, a.z);
+            let b_base = Vector3::new(b.x, base_height, b.z);
+            verts_out.extend_from_slice(&[b, a, a_base]);
+            if a_base != b_base {
+                verts_out.extend_from_slice(&[b, a_base, b_base]);
+            }
+            face_points[face].push(along_face(a, face));
+            face_points[face].push(along_face(b, face));
         }
     }
 
-    if transition_top || transition_bottom || transition_left || transition_right {
-        if transition_top {
-            let z = 0;
-            for x in (1..(lod_chunk_size)).step_by(2) {
-                let i  = z * (lod_chunk_size ) + x;
-                let left_vert = vertices_out[i - 1];
-                let right_vert = vertices_out[i + 1];
-                vertices_out[i].y = 0.5 * (left_vert.y + right_vert.y);
+    if cap {
+        // The floor's outline picks up every wall corner so the two meet without gaps
+        let corners = [Vector3::new(0.0, base_height, size), Vector3::new(size, base_height, 0.0), Vector3::new(0.0, base_height, 0.0), Vector3::new(size, base_height, size)];
+        let directions = [Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.0)];
+        let mut outline:Vec<Vector3> = Vec::new();
+        for face in [2, 1, 3, 0].iter().cloned() {
+            let points = &mut face_points[face];
+            points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            points.dedup();
+            // The last point of each face is the first of the next
+            for u in points[..points.len() - 1].iter() {
+                outline.push(corners[face] + directions[face] * *u);
             }
         }
-    
-       if transition_bottom {
-            let z = lod_chunk_size;
-            for x in (1..(lod_chunk_size)).step_by(2) {
-                let i  = z * (lod_chunk_size + 1) + x;
-                let left_vert = vertices_out[i - 1];
-                let right_vert = vertices_out[i + 1];
-                vertices_out[i].y = 0.5 * (left_vert.y + right_vert.y);
-            }
+        let center = Vector3::new(size * 0.5, base_height, size * 0.5);
+        for i in 0..outline.len() {
+            verts_out.extend_from_slice(&[center, outline[(i + 1) % outline.len()], outline[i]]);
         }
-    
-        if transition_left {
-            let x = 0;
-            for z in (1..lod_chunk_size).step_by(2) {
-                let i  = z * (lod_chunk_size + 1) + x;
-                let top_vert = vertices_out[i - lod_chunk_size - 1];
-                let bottom_vert = vertices_out[i + lod_chunk_size + 1];
-                vertices_out[i].y = 0.5 * (top_vert.y + bottom_vert.y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Rolling hills with a few cliffs, so seams cross both gentle and steep slopes
+    fn test_heights (map_width:usize, map_depth:usize) -> Vec<i32> {
+        let mut heights = Vec::new();
+        for z in 0..map_depth {
+            for x in 0..map_width {
+                heights.push(((x * 7 + z * 13) % 5) as i32 + (x / 3) as i32 - (z / 4) as i32 * 2 + 10);
             }
         }
-    
-        if transition_right {
-            let x = lod_chunk_size;
-            for z in (1..lod_chunk_size).step_by(2) {
-                let i  = z * (lod_chunk_size + 1) + x;
-                let top_vert = vertices_out[i - lod_chunk_size - 1];
-                let bottom_vert = vertices_out[i + lod_chunk_size + 1];
-                vertices_out[i].y = 0.5 * (top_vert.y + bottom_vert.y);
+        return heights;
+    }
+
+    // Meshes every chunk and returns the edges used by only one triangle, in map coordinates scaled by 4
+    fn open_edges (heights:&Vec<i32>, chunk_size:usize, num_chunks_x:usize, num_chunks_z:usize, lod_list:&Vec<usize>) -> Vec<((i64,i64,i64),(i64,i64,i64))> {
+        let key = |v:Vector3| -> (i64,i64,i64) {
+            return ((v.x * 4.0).round() as i64, (v.y * 4.0).round() as i64, (v.z * 4.0).round() as i64);
+        };
+        let mut edge_count:HashMap<((i64,i64,i64),(i64,i64,i64)),usize> = HashMap::new();
+        for chunk_id in 0..num_chunks_x * num_chunks_z {
+            let offset = Vector3::new(
+                ((chunk_id % num_chunks_x) * chunk_size) as f32,
+                0.0,
+                ((chunk_id / num_chunks_x) * chunk_size) as f32
+            );
+            let verts = generate_chunk_mesh_from_heightmap(heights, chunk_id, chunk_size, num_chunks_x, num_chunks_z, lod_list);
+            assert_eq!(verts.len() % 3, 0);
+            for triangle in verts.chunks(3) {
+                for i in 0..3 {
+                    let a = key(triangle[i] + offset);
+                    let b = key(triangle[(i + 1) % 3] + offset);
+                    let edge = if a < b { (a, b) } else { (b, a) };
+                    *edge_count.entry(edge).or_insert(0) += 1;
+                }
             }
         }
+        return edge_count.into_iter().filter(|(_, count)| *count == 1).map(|(edge, _)| edge).collect();
+    }
+
+    // The surface is only allowed to be open along the outside of the map
+    fn assert_closed_seams (chunk_size:usize, num_chunks_x:usize, num_chunks_z:usize, lod_list:&Vec<usize>) {
+        let map_width = chunk_size * num_chunks_x;
+        let map_depth = chunk_size * num_chunks_z;
+        let heights = test_heights(map_width, map_depth);
+        let (max_x, max_z) = (map_width as i64 * 4, map_depth as i64 * 4);
+        let on_map_border = |p:(i64,i64,i64)| p.0 == 0 || p.0 == max_x || p.2 == 0 || p.2 == max_z;
+        let inner_edges:Vec<_> = open_edges(&heights, chunk_size, num_chunks_x, num_chunks_z, lod_list)
+            .into_iter()
+            .filter(|(a, b)| !(on_map_border(*a) && on_map_border(*b) && (a.0 == b.0 || a.2 == b.2)))
+            .collect();
+        assert!(inner_edges.is_empty(), "chunk_size {} lods {:?}: open edges {:?}", chunk_size, lod_list, inner_edges);
     }
 
-    for z in 0..lod_chunk_size {
-        for x in 0..lod_chunk_size {
-            let i = z * lod_chunk_size + x as usize; // Index of each vertex in vertex array at (x,z)
-            let v = i + z as usize; // v is the index of the top left corner of each square
-            let corner:[usize;4] = [
-                v,
-                v + 1,
-                v + lod_chunk_size + 1,
-                v + lod_chunk_size + 2];
+    #[test]
+    fn same_lod_chunks_are_closed () {
+        assert_closed_seams(8, 2, 2, &vec![0; 4]);
+        assert_closed_seams(8, 2, 2, &vec![2; 4]);
+    }
+
+    #[test]
+    fn mixed_lod_seams_are_closed () {
+        assert_closed_seams(8, 3, 2, &vec![0, 1, 2, 1, 2, 3]);
+        assert_closed_seams(16, 2, 2, &vec![3, 2, 4, 3]);
+    }
 
-            // First triangle
+    #[test]
+    fn non_power_of_two_chunk_seams_are_closed () {
+        assert_closed_seams(6, 3, 2, &vec![0, 1, 2, 1, 2, 2]);
+        assert_closed_seams(12, 2, 3, &vec![1, 2, 2, 3, 3, 2]);
+    }
 
-            indices_out.push(corner[0]);
-            indices_out.push(corner[1]);
-            indices_out.push(corner[2]);
+    #[test]
+    fn limited_lod_steps_close_seams () {
+        // A chunk two levels coarser than its neighbour is brought down to one level
+        let mut lod_list:Vec<usize> = vec![0, 2];
+        limit_lod_steps(&mut lod_list, 2, 1);
+        assert_eq!(lod_list, vec![0, 1]);
+        assert_closed_seams(8, 2, 1, &lod_list);
+    }
 
-            // Second triangle
+    #[test]
+    fn oversized_lods_are_clamped_at_seams () {
+        // Chunks of 6 top out at LOD 2, so 9 and 3 mesh like 2
+        assert_closed_seams(6, 3, 2, &vec![0, 1, 9, 1, 3, 2]);
 
-            indices_out.push(corner[1]);
-            indices_out.push(corner[3]);
-            indices_out.push(corner[2]);
-        }
+        let mut lod_list:Vec<usize> = vec![0, 7, 9, 9, 9, 9].into_iter().map(|lod| clamp_lod(lod, 6)).collect();
+        limit_lod_steps(&mut lod_list, 3, 2);
+        assert_eq!(lod_list, vec![0, 1, 2, 1, 2, 2]);
+        assert_closed_seams(6, 3, 2, &lod_list);
     }
 }
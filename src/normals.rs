@@ -0,0 +1,58 @@
+use gdnative::prelude::Vector3;
+use std::collections::HashMap;
+
+// Faces meeting at more than this many degrees get a hard edge between them. Marching cubes bevels steps at 45 degrees
+pub const DEFAULT_CREASE_ANGLE:f32 = 40.0;
+
+// Triangles are wound clockwise, so this points out of the front face. Its length is twice the face's area
+fn face_normal (a:Vector3, b:Vector3, c:Vector3) -> Vector3 {
+    return (c - a).cross(b - a);
+}
+
+// Gives each corner of a triangle the area weighted average normal of the faces around its vertex that are within
+// `crease_angle` degrees of the triangle. Vertices on a crease are split so each side keeps its own normal
+pub fn crease_normals (vertices:&Vec<Vector3>, indices:&Vec<usize>, crease_angle:f32) -> (Vec<Vector3>, Vec<Vector3>, Vec<usize>) {
+    let face_count = indices.len() / 3;
+    let mut weighted:Vec<Vector3> = Vec::with_capacity(face_count);
+    let mut unit:Vec<Vector3> = Vec::with_capacity(face_count);
+    let mut vertex_faces:Vec<Vec<usize>> = vec![Vec::new(); vertices.len()];
+    for face in 0..face_count {
+        let normal = face_normal(vertices[indices[face * 3]], vertices[indices[face * 3 + 1]], vertices[indices[face * 3 + 2]]);
+        let length = normal.length();
+        weighted.push(normal);
+        unit.push(if length > 0.0 { normal / length } else { Vector3::new(0.0, 1.0, 0.0) });
+        for corner in 0..3 {
+            vertex_faces[indices[face * 3 + corner]].push(face);
+        }
+    }
+
+    // A little slack keeps faces that are coplanar up to rounding together when the angle is zero
+    let min_dot = crease_angle.clamp(0.0, 180.0).to_radians().cos() - 1e-4;
+
+    let mut vertices_out:Vec<Vector3> = Vec::new();
+    let mut normals_out:Vec<Vector3> = Vec::new();
+    let mut indices_out:Vec<usize> = Vec::with_capacity(indices.len());
+    let mut corner_map:HashMap<(usize,[i32; 3]),usize> = HashMap::new();
+    for face in 0..face_count {
+        for corner in 0..3 {
+            let index = indices[face * 3 + corner];
+            let mut sum = Vector3::new(0.0, 0.0, 0.0);
+            for other in vertex_faces[index].iter() {
+                if unit[face].dot(unit[*other]) >= min_dot {
+                    sum += weighted[*other];
+                }
+            }
+            let length = sum.length();
+            let normal = if length > 0.0 { sum / length } else { unit[face] };
+
+            let key = (index, [(normal.x * 1024.0).round() as i32, (normal.y * 1024.0).round() as i32, (normal.z * 1024.0).round() as i32]);
+            let new_index = *corner_map.entry(key).or_insert_with(|| {
+                vertices_out.push(vertices[index]);
+                normals_out.push(normal);
+                vertices_out.len() - 1
+            });
+            indices_out.push(new_index);
+        }
+    }
+    return (vertices_out, normals_out, indices_out);
+}
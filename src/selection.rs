@@ -0,0 +1,144 @@
+use gdnative::prelude::Vector2;
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum SelectionOp {
+	Replace,
+	Add,
+	Subtract,
+	Intersect,
+}
+
+impl SelectionOp {
+	pub fn from_i32 (op:i32) -> Self {
+		match op {
+			1 => SelectionOp::Add,
+			2 => SelectionOp::Subtract,
+			3 => SelectionOp::Intersect,
+			_ => SelectionOp::Replace
+		}
+	}
+}
+
+// Per-cell selection weights in [0,1], laid out like the height array
+#[derive(Clone,Debug)]
+pub struct SelectionMask {
+	pub size_x:usize,
+	pub size_z:usize,
+	pub weights:Vec<f32>,
+}
+
+impl SelectionMask {
+	pub fn new (size_x:usize, size_z:usize) -> Self {
+		SelectionMask {
+			size_x: size_x,
+			size_z: size_z,
+			weights: vec![0.0; size_x * size_z],
+		}
+	}
+
+	pub fn from_fn<F:Fn(usize, usize) -> f32> (size_x:usize, size_z:usize, weight_at:F) -> Self {
+		let mut mask = SelectionMask::new(size_x, size_z);
+		for z in 0..size_z {
+			for x in 0..size_x {
+				mask.weights[z * size_x + x] = weight_at(x, z).clamp(0.0, 1.0);
+			}
+		}
+		return mask;
+	}
+
+	pub fn rect (size_x:usize, size_z:usize, min:Vector2, max:Vector2) -> Self {
+		return SelectionMask::from_fn(size_x, size_z, |x, z| {
+			let inside = x as f32 >= min.x && x as f32 <= max.x && z as f32 >= min.y && z as f32 <= max.y;
+			return if inside { 1.0 } else { 0.0 };
+		});
+	}
+
+	pub fn ellipse (size_x:usize, size_z:usize, center:Vector2, radius:Vector2) -> Self {
+		let radius = Vector2::new(radius.x.max(0.5), radius.y.max(0.5));
+		return SelectionMask::from_fn(size_x, size_z, |x, z| {
+			let dx = (x as f32 - center.x) / radius.x;
+			let dz = (z as f32 - center.y) / radius.y;
+			return if dx * dx + dz * dz <= 1.0 { 1.0 } else { 0.0 };
+		});
+	}
+
+	// Even-odd fill of a closed polygon
+	pub fn polygon (size_x:usize, size_z:usize, points:&Vec<Vector2>) -> Self {
+		if points.len() < 3 {
+			return SelectionMask::new(size_x, size_z);
+		}
+		return SelectionMask::from_fn(size_x, size_z, |x, z| {
+			let px = x as f32;
+			let pz = z as f32;
+			let mut inside = false;
+			let mut j = points.len() - 1;
+			for i in 0..points.len() {
+				let a = points[i];
+				let b = points[j];
+				if (a.y > pz) != (b.y > pz) && px < (b.x - a.x) * (pz - a.y) / (b.y - a.y) + a.x {
+					inside = !inside;
+				}
+				j = i;
+			}
+			return if inside { 1.0 } else { 0.0 };
+		});
+	}
+
+	pub fn weight (&self, index:usize) -> f32 {
+		return self.weights[index];
+	}
+
+	pub fn invert (&mut self) {
+		for w in self.weights.iter_mut() {
+			*w = 1.0 - *w;
+		}
+	}
+
+	pub fn combine (&mut self, other:&SelectionMask, op:SelectionOp) {
+		for i in 0..self.weights.len().min(other.weights.len()) {
+			let a = self.weights[i];
+			let b = other.weights[i];
+			self.weights[i] = match op {
+				SelectionOp::Replace => b,
+				SelectionOp::Add => a.max(b),
+				SelectionOp::Subtract => a * (1.0 - b),
+				SelectionOp::Intersect => a.min(b),
+			};
+		}
+	}
+
+	// Softens the edges with two passes of a separable box blur, which approximates a tent filter
+	pub fn feather (&mut self, radius:f32) {
+		let radius = radius.round() as i32;
+		if radius <= 0 {
+			return;
+		}
+		for _pass in 0..2 {
+			self.blur_axis(radius, true);
+			self.blur_axis(radius, false);
+		}
+	}
+
+	fn blur_axis (&mut self, radius:i32, along_x:bool) {
+		let mut blurred = vec![0.0; self.weights.len()];
+		let (length, lines) = if along_x { (self.size_x, self.size_z) } else { (self.size_z, self.size_x) };
+		let index = |line:usize, i:usize| -> usize {
+			return if along_x { line * self.size_x + i } else { i * self.size_x + line };
+		};
+
+		for line in 0..lines {
+			for i in 0..length {
+				let mut total:f32 = 0.0;
+				let mut count:f32 = 0.0;
+				for offset in -radius..=radius {
+					let j = i as i32 + offset;
+					if j < 0 || j >= length as i32 { continue; }
+					total += self.weights[index(line, j as usize)];
+					count += 1.0;
+				}
+				blurred[index(line, i)] = total / count;
+			}
+		}
+		self.weights = blurred;
+	}
+}
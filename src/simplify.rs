@@ -0,0 +1,380 @@
+use gdnative::prelude::Vector3;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+// Positions are multiples of a quarter unit, so they can be compared exactly once scaled
+fn grid_key (value:f32) -> i64 {
+    return (value * 4.0).round() as i64;
+}
+
+fn cross (a:Vector3, b:Vector3) -> Vector3 {
+    return Vector3::new(a.y * b.z - a.z * b.y, a.z * b.x - a.x * b.z, a.x * b.y - a.y * b.x);
+}
+
+fn face_normal (a:Vector3, b:Vector3, c:Vector3) -> Vector3 {
+    return cross(b - a, c - a);
+}
+
+// Keeps the vertices that are still referenced and renumbers the indices
+fn compact (vertices:&Vec<Vector3>, triangles:&Vec<[usize; 3]>) -> (Vec<Vector3>, Vec<usize>) {
+    let mut remap:Vec<Option<usize>> = vec![None; vertices.len()];
+    let mut vertices_out:Vec<Vector3> = Vec::new();
+    let mut indices_out:Vec<usize> = Vec::with_capacity(triangles.len() * 3);
+    for triangle in triangles.iter() {
+        for index in triangle.iter() {
+            let new_index = match remap[*index] {
+                Some(new_index) => new_index,
+                None => {
+                    let new_index = vertices_out.len();
+                    remap[*index] = Some(new_index);
+                    vertices_out.push(vertices[*index]);
+                    new_index
+                }
+            };
+            indices_out.push(new_index);
+        }
+    }
+    return (vertices_out, indices_out);
+}
+
+#[derive(Clone,Copy)]
+struct FlatQuad {
+    y:i64,
+    x0:i64,
+    z0:i64,
+    x1:i64,
+    z1:i64,
+    triangles:[usize; 2],
+    color:Option<usize>, // None when the corners differ in color, which keeps the quad out of any merge
+}
+
+// Joins the two triangles marching cubes emits for each flat topped cell into larger rectangles of the same color.
+// Every vertex on a rectangle's outline that other triangles use is kept, so outlines and neighbours don't move.
+// Vertices on the chunk border are always kept too, since the neighbouring chunk is meshed on its own
+pub fn merge_flat_quads (vertices:&Vec<Vector3>, indices:&Vec<usize>, colors:&Vec<usize>, chunk_size:usize) -> (Vec<Vector3>, Vec<usize>) {
+    let triangles:Vec<[usize; 3]> = indices.chunks(3).map(|t| [t[0], t[1], t[2]]).collect();
+
+    // Upward facing horizontal triangles grouped by the rectangle they cover
+    let mut halves:HashMap<(i64,i64,i64,i64,i64),Vec<usize>> = HashMap::new();
+    for (t, triangle) in triangles.iter().enumerate() {
+        let points = [vertices[triangle[0]], vertices[triangle[1]], vertices[triangle[2]]];
+        let y = grid_key(points[0].y);
+        if grid_key(points[1].y) != y || grid_key(points[2].y) != y || face_normal(points[0], points[1], points[2]).y >= 0.0 {
+            continue;
+        }
+        let x0 = points.iter().map(|p| grid_key(p.x)).min().unwrap();
+        let x1 = points.iter().map(|p| grid_key(p.x)).max().unwrap();
+        let z0 = points.iter().map(|p| grid_key(p.z)).min().unwrap();
+        let z1 = points.iter().map(|p| grid_key(p.z)).max().unwrap();
+        halves.entry((y, x0, z0, x1, z1)).or_insert(Vec::new()).push(t);
+    }
+
+    let mut quads:Vec<FlatQuad> = Vec::new();
+    for (key, group) in halves.iter() {
+        if group.len() != 2 {
+            continue;
+        }
+        // Both halves of a cell are right triangles whose corners all lie on the corners of the rectangle
+        let (y, x0, z0, x1, z1) = *key;
+        let mut corners:HashMap<(i64,i64),usize> = HashMap::new();
+        let mut on_corners = true;
+        for t in group.iter() {
+            for index in triangles[*t].iter() {
+                let x = grid_key(vertices[*index].x);
+                let z = grid_key(vertices[*index].z);
+                on_corners &= (x == x0 || x == x1) && (z == z0 || z == z1);
+                corners.insert((x, z), *index);
+            }
+        }
+        if !on_corners || corners.len() != 4 {
+            continue;
+        }
+        let first = colors[*corners.values().next().unwrap()];
+        let color = if corners.values().all(|index| colors[*index] == first) { Some(first) } else { None };
+        quads.push(FlatQuad { y, x0, z0, x1, z1, triangles: [group[0], group[1]], color });
+    }
+    quads.sort_by_key(|quad| (quad.y, quad.z0, quad.x0));
+    let quad_at:HashMap<(i64,i64,i64),usize> = quads.iter().enumerate().map(|(i, quad)| ((quad.y, quad.x0, quad.z0), i)).collect();
+
+    // Greedy merge: grow each rectangle along x first, then add whole rows along z
+    let mut merged:Vec<bool> = vec![false; quads.len()];
+    let mut rectangles:Vec<(usize, i64, i64)> = Vec::new(); // First quad and the far corner of the rectangle
+    for start in 0..quads.len() {
+        if merged[start] {
+            continue;
+        }
+        merged[start] = true;
+        let quad = quads[start];
+        let mut x_end = quad.x1;
+        let mut z_end = quad.z1;
+        if quad.color.is_some() {
+            while let Some(next) = quad_at.get(&(quad.y, x_end, quad.z0)) {
+                let next_quad = quads[*next];
+                if merged[*next] || next_quad.color != quad.color || next_quad.z1 != quad.z1 {
+                    break;
+                }
+                merged[*next] = true;
+                x_end = next_quad.x1;
+            }
+            loop {
+                let mut row:Vec<usize> = Vec::new();
+                let mut x = quad.x0;
+                let mut row_end = None;
+                while x < x_end {
+                    match quad_at.get(&(quad.y, x, z_end)) {
+                        Some(next) if !merged[*next] && quads[*next].color == quad.color
+                            && row_end.map_or(true, |end| end == quads[*next].z1) => {
+                            row_end = Some(quads[*next].z1);
+                            row.push(*next);
+                            x = quads[*next].x1;
+                        }
+                        _ => break,
+                    }
+                }
+                if x != x_end || row.len() == 0 {
+                    break;
+                }
+                for next in row.iter() {
+                    merged[*next] = true;
+                }
+                z_end = row_end.unwrap();
+            }
+        }
+        rectangles.push((start, x_end, z_end));
+    }
+
+    // Vertices other triangles or other rectangles rely on, indexed by the lines they lie on
+    let mut in_quad:Vec<bool> = vec![false; triangles.len()];
+    for quad in quads.iter() {
+        in_quad[quad.triangles[0]] = true;
+        in_quad[quad.triangles[1]] = true;
+    }
+    let mut needed:HashSet<usize> = HashSet::new();
+    for (t, triangle) in triangles.iter().enumerate() {
+        if !in_quad[t] {
+            needed.extend(triangle.iter());
+        }
+    }
+    let mut rows:HashMap<(i64,i64),Vec<(i64,usize)>> = HashMap::new(); // (y, z) -> x
+    let mut columns:HashMap<(i64,i64),Vec<(i64,usize)>> = HashMap::new(); // (y, x) -> z
+    let mut corner_at:HashMap<(i64,i64,i64),usize> = HashMap::new();
+    let border = grid_key(chunk_size as f32);
+    for quad in quads.iter() {
+        for t in quad.triangles.iter() {
+            for index in triangles[*t].iter() {
+                let (x, z) = (grid_key(vertices[*index].x), grid_key(vertices[*index].z));
+                corner_at.insert((quad.y, x, z), *index);
+                if x == 0 || z == 0 || x == border || z == border {
+                    needed.insert(*index);
+                }
+            }
+        }
+    }
+    for (start, x_end, z_end) in rectangles.iter() {
+        let quad = quads[*start];
+        for (x, z) in [(quad.x0, quad.z0), (*x_end, quad.z0), (*x_end, *z_end), (quad.x0, *z_end)].iter() {
+            needed.insert(corner_at[&(quad.y, *x, *z)]);
+        }
+    }
+    for index in needed.iter() {
+        let vertex = vertices[*index];
+        let (x, y, z) = (grid_key(vertex.x), grid_key(vertex.y), grid_key(vertex.z));
+        rows.entry((y, z)).or_insert(Vec::new()).push((x, *index));
+        columns.entry((y, x)).or_insert(Vec::new()).push((z, *index));
+    }
+
+    let mut triangles_out:Vec<[usize; 3]> = Vec::new();
+    for (t, triangle) in triangles.iter().enumerate() {
+        if !in_quad[t] {
+            triangles_out.push(*triangle);
+        }
+    }
+    for (start, x_end, z_end) in rectangles.iter() {
+        let quad = quads[*start];
+        if *x_end == quad.x1 && *z_end == quad.z1 {
+            triangles_out.push(triangles[quad.triangles[0]]);
+            triangles_out.push(triangles[quad.triangles[1]]);
+            continue;
+        }
+        // Outline of the rectangle, corners included, walking x then z
+        let side = |line:Option<&Vec<(i64,usize)>>, from:i64, to:i64| -> Vec<usize> {
+            let mut points:Vec<(i64,usize)> = line.map_or(Vec::new(), |line| line.iter()
+                .filter(|(value, _)| *value > from.min(to) && *value < from.max(to)).cloned().collect());
+            points.sort_by_key(|(value, _)| if from < to { *value } else { -*value });
+            return points.iter().map(|(_, index)| *index).collect();
+        };
+        let mut outline:Vec<usize> = Vec::new();
+        outline.push(corner_at[&(quad.y, quad.x0, quad.z0)]);
+        outline.extend(side(rows.get(&(quad.y, quad.z0)), quad.x0, *x_end));
+        outline.push(corner_at[&(quad.y, *x_end, quad.z0)]);
+        outline.extend(side(columns.get(&(quad.y, *x_end)), quad.z0, *z_end));
+        outline.push(corner_at[&(quad.y, *x_end, *z_end)]);
+        outline.extend(side(rows.get(&(quad.y, *z_end)), *x_end, quad.x0));
+        outline.push(corner_at[&(quad.y, quad.x0, *z_end)]);
+        outline.extend(side(columns.get(&(quad.y, quad.x0)), *z_end, quad.z0));
+        triangulate_convex(vertices, &outline, &mut triangles_out);
+    }
+    return compact(vertices, &triangles_out);
+}
+
+// Ear clips a convex polygon lying flat in the xz plane that may have points along its sides, facing up like the cells around it
+fn triangulate_convex (vertices:&Vec<Vector3>, outline:&Vec<usize>, triangles_out:&mut Vec<[usize; 3]>) {
+    let mut polygon = outline.clone();
+    let turn = |polygon:&Vec<usize>, i:usize| -> f32 {
+        let count = polygon.len();
+        let prev = vertices[polygon[(i + count - 1) % count]];
+        let point = vertices[polygon[i]];
+        let next = vertices[polygon[(i + 1) % count]];
+        return face_normal(prev, point, next).y;
+    };
+    let push = |triangles_out:&mut Vec<[usize; 3]>, a:usize, b:usize, c:usize| {
+        if face_normal(vertices[a], vertices[b], vertices[c]).y < 0.0 {
+            triangles_out.push([a, b, c]);
+        } else {
+            triangles_out.push([a, c, b]);
+        }
+    };
+    while polygon.len() > 3 {
+        let count = polygon.len();
+        // Clipping a corner next to a point on a side uses that point up; clipping any other corner could strand a row of points
+        let mut ear = None;
+        for i in 0..count {
+            if turn(&polygon, i).abs() > 1e-6 {
+                let flat_neighbour = turn(&polygon, (i + count - 1) % count).abs() <= 1e-6 || turn(&polygon, (i + 1) % count).abs() <= 1e-6;
+                if flat_neighbour || ear.is_none() {
+                    ear = Some(i);
+                    if flat_neighbour {
+                        break;
+                    }
+                }
+            }
+        }
+        let i = match ear {
+            Some(i) => i,
+            None => return,
+        };
+        push(triangles_out, polygon[(i + count - 1) % count], polygon[i], polygon[(i + 1) % count]);
+        polygon.remove(i);
+    }
+    if polygon.len() == 3 && turn(&polygon, 0).abs() > 1e-6 {
+        push(triangles_out, polygon[0], polygon[1], polygon[2]);
+    }
+}
+
+// Collapses edges while every original vertex stays within `max_error` of the surface. Vertices on open edges stay put,
+// and edges are only collapsed between vertices of the same color so color borders survive
+pub fn decimate (vertices:&Vec<Vector3>, indices:&Vec<usize>, colors:&Vec<usize>, max_error:f32) -> (Vec<Vector3>, Vec<usize>) {
+    let mut triangles:Vec<[usize; 3]> = indices.chunks(3).map(|t| [t[0], t[1], t[2]]).collect();
+    let mut alive:Vec<bool> = vec![true; triangles.len()];
+    let mut vertex_triangles:Vec<Vec<usize>> = vec![Vec::new(); vertices.len()];
+    for (t, triangle) in triangles.iter().enumerate() {
+        for index in triangle.iter() {
+            vertex_triangles[*index].push(t);
+        }
+    }
+
+    let mut edge_uses:HashMap<(usize,usize),u32> = HashMap::new();
+    for triangle in triangles.iter() {
+        for i in 0..3 {
+            let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+            *edge_uses.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+        }
+    }
+    let mut locked:Vec<bool> = vec![false; vertices.len()];
+    for ((a, b), uses) in edge_uses.iter() {
+        if *uses != 2 {
+            locked[*a] = true;
+            locked[*b] = true;
+        }
+    }
+
+    // Original positions each remaining vertex stands in for
+    let mut represented:Vec<Vec<Vector3>> = vertices.iter().map(|vertex| vec![*vertex]).collect();
+    let mut removed:Vec<bool> = vec![false; vertices.len()];
+
+    let neighbours = |vertex_triangles:&Vec<Vec<usize>>, triangles:&Vec<[usize; 3]>, vertex:usize| -> HashSet<usize> {
+        let mut set:HashSet<usize> = HashSet::new();
+        for t in vertex_triangles[vertex].iter() {
+            set.extend(triangles[*t].iter().filter(|index| **index != vertex));
+        }
+        return set;
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for u in 0..vertices.len() {
+            if removed[u] || locked[u] || vertex_triangles[u].len() == 0 {
+                continue;
+            }
+            let u_neighbours = neighbours(&vertex_triangles, &triangles, u);
+            for v in u_neighbours.iter() {
+                let v = *v;
+                if colors[u] != colors[v] {
+                    continue;
+                }
+                // Keep the mesh manifold: u and v may only share the vertices opposite their shared edge
+                let shared_triangles:Vec<usize> = vertex_triangles[u].iter().filter(|t| triangles[**t].contains(&v)).cloned().collect();
+                let common = u_neighbours.intersection(&neighbours(&vertex_triangles, &triangles, v)).count();
+                if common != shared_triangles.len() {
+                    continue;
+                }
+
+                let mut new_faces:Vec<(Vector3, Vector3)> = Vec::new(); // Point on and normal of each face after the collapse
+                let mut valid = true;
+                for t in vertex_triangles[u].iter() {
+                    if shared_triangles.contains(t) {
+                        continue;
+                    }
+                    let triangle = triangles[*t];
+                    let old_normal = face_normal(vertices[triangle[0]], vertices[triangle[1]], vertices[triangle[2]]);
+                    let moved:Vec<Vector3> = triangle.iter().map(|index| if *index == u { vertices[v] } else { vertices[*index] }).collect();
+                    let new_normal = face_normal(moved[0], moved[1], moved[2]);
+                    let length = (new_normal.x * new_normal.x + new_normal.y * new_normal.y + new_normal.z * new_normal.z).sqrt();
+                    if length < 1e-6 || old_normal.x * new_normal.x + old_normal.y * new_normal.y + old_normal.z * new_normal.z <= 0.0 {
+                        valid = false;
+                        break;
+                    }
+                    new_faces.push((moved[0], new_normal * (1.0 / length)));
+                }
+                if !valid {
+                    continue;
+                }
+                let within_error = represented[u].iter().chain(represented[v].iter()).all(|point| {
+                    new_faces.iter().all(|(origin, normal)| {
+                        let offset = *point - *origin;
+                        (offset.x * normal.x + offset.y * normal.y + offset.z * normal.z).abs() <= max_error
+                    })
+                });
+                if !within_error {
+                    continue;
+                }
+
+                for t in vertex_triangles[u].clone().iter() {
+                    if shared_triangles.contains(t) {
+                        alive[*t] = false;
+                        for index in triangles[*t].iter() {
+                            vertex_triangles[*index].retain(|other| other != t);
+                        }
+                    } else {
+                        for index in triangles[*t].iter_mut() {
+                            if *index == u {
+                                *index = v;
+                            }
+                        }
+                        vertex_triangles[v].push(*t);
+                    }
+                }
+                vertex_triangles[u].clear();
+                let points = std::mem::take(&mut represented[u]);
+                represented[v].extend(points);
+                removed[u] = true;
+                changed = true;
+                break;
+            }
+        }
+    }
+
+    let remaining:Vec<[usize; 3]> = triangles.iter().enumerate().filter(|(t, _)| alive[*t]).map(|(_, triangle)| *triangle).collect();
+    return compact(vertices, &remaining);
+}
@@ -0,0 +1,84 @@
+use gdnative::prelude::Color;
+
+pub const MAX_SPLAT_CHANNELS:usize = 8;
+// Weights of one cell are bytes that add up to this
+pub const FULL_WEIGHT:u8 = 255;
+
+// One weight per material. Only the first `channels` entries are used, the rest stay zero
+pub type SplatWeights = [u8; MAX_SPLAT_CHANNELS];
+
+pub fn full_weight (channel:usize) -> SplatWeights {
+    let mut weights:SplatWeights = [0; MAX_SPLAT_CHANNELS];
+    weights[channel.min(MAX_SPLAT_CHANNELS - 1)] = FULL_WEIGHT;
+    return weights;
+}
+
+// Rounds fractions that add up to one into bytes that add up to FULL_WEIGHT.
+// The rounding error goes to the heaviest channel, which can always absorb it
+fn quantize (fractions:&[f32; MAX_SPLAT_CHANNELS], channels:usize) -> SplatWeights {
+    let mut weights:SplatWeights = [0; MAX_SPLAT_CHANNELS];
+    let mut total:i32 = 0;
+    let mut heaviest = 0;
+    for i in 0..channels {
+        weights[i] = (fractions[i].clamp(0.0, 1.0) * FULL_WEIGHT as f32).round() as u8;
+        total += weights[i] as i32;
+        if weights[i] > weights[heaviest] {
+            heaviest = i;
+        }
+    }
+    weights[heaviest] = (weights[heaviest] as i32 + FULL_WEIGHT as i32 - total).clamp(0, FULL_WEIGHT as i32) as u8;
+    return weights;
+}
+
+// Rescales the first `channels` weights to add up to FULL_WEIGHT and clears the rest.
+// Cells with no weight left fall back to the first material
+pub fn normalize (weights:&SplatWeights, channels:usize) -> SplatWeights {
+    let channels = channels.clamp(1, MAX_SPLAT_CHANNELS);
+    let total:f32 = weights[..channels].iter().map(|w| *w as f32).sum();
+    if total <= 0.0 {
+        return full_weight(0);
+    }
+    let mut fractions = [0.0; MAX_SPLAT_CHANNELS];
+    for i in 0..channels {
+        fractions[i] = weights[i] as f32 / total;
+    }
+    return quantize(&fractions, channels);
+}
+
+// Moves `channel` towards full weight by `strength` (0 to 1), or towards zero when strength is negative.
+// The other channels give up or take the difference in proportion to their current weights, so the total stays the same
+pub fn redistribute (weights:&SplatWeights, channels:usize, channel:usize, strength:f32) -> SplatWeights {
+    let channels = channels.clamp(1, MAX_SPLAT_CHANNELS);
+    if channel >= channels {
+        return *weights;
+    }
+    // With a single channel there is nowhere for the weight to go
+    if channels == 1 {
+        return full_weight(0);
+    }
+    let strength = strength.clamp(-1.0, 1.0);
+    let current = weights[channel] as f32 / FULL_WEIGHT as f32;
+    let target = if strength >= 0.0 { current + (1.0 - current) * strength } else { current * (1.0 + strength) };
+    let others:f32 = (0..channels).filter(|i| *i != channel).map(|i| weights[i] as f32 / FULL_WEIGHT as f32).sum();
+
+    let mut fractions = [0.0; MAX_SPLAT_CHANNELS];
+    fractions[channel] = target;
+    for i in 0..channels {
+        if i == channel { continue; }
+        fractions[i] = if others > 0.0 {
+            weights[i] as f32 / FULL_WEIGHT as f32 / others * (1.0 - target)
+        } else {
+            (1.0 - target) / (channels - 1) as f32
+        };
+    }
+    return quantize(&fractions, channels);
+}
+
+// Four channels starting at 4 * layer as RGBA, for vertex colors and splat textures
+pub fn pack_layer (weights:&SplatWeights, layer:usize) -> Color {
+    let channel = |i:usize| -> f32 {
+        let i = layer * 4 + i;
+        if i < MAX_SPLAT_CHANNELS { weights[i] as f32 / FULL_WEIGHT as f32 } else { 0.0 }
+    };
+    return Color::rgba(channel(0), channel(1), channel(2), channel(3));
+}
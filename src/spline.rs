@@ -0,0 +1,165 @@
+use super::structs::*;
+use gdnative::prelude::*;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct SplinePoint {
+	pub position:Vector2,
+	pub height:f32,
+	pub width:f32,
+}
+
+impl SplinePoint {
+	pub fn new (position:Vector2, height:f32, width:f32) -> Self {
+		SplinePoint { position: position, height: height, width: width }
+	}
+
+	fn lerp (&self, other:&SplinePoint, t:f32) -> SplinePoint {
+		SplinePoint::new(
+			self.position + (other.position - self.position) * t,
+			self.height + (other.height - self.height) * t,
+			self.width + (other.width - self.width) * t
+		)
+	}
+}
+
+#[derive(Clone,Debug)]
+pub struct Spline {
+	pub points:Vec<SplinePoint>,
+	pub smooth:bool, // Catmull-Rom through the points, otherwise a polyline
+	pub carve_mode:i32, // 0 carve and fill, 1 carve only, 2 fill only
+	pub embankment:f32, // Width of the slope between the road bed and the terrain
+	pub paint_color:Option<Color8>,
+}
+
+impl Spline {
+	pub fn new () -> Self {
+		Spline {
+			points: Vec::new(),
+			smooth: true,
+			carve_mode: 0,
+			embankment: 0.0,
+			paint_color: None,
+		}
+	}
+
+	pub fn max_width (&self) -> f32 {
+		let mut max_width:f32 = 0.0;
+		for point in self.points.iter() {
+			max_width = max_width.max(point.width);
+		}
+		return max_width;
+	}
+
+	fn point_at (&self, segment:usize, t:f32) -> SplinePoint {
+		let last = self.points.len() - 1;
+		let p1 = self.points[segment];
+		let p2 = self.points[(segment + 1).min(last)];
+		if !self.smooth {
+			return p1.lerp(&p2, t);
+		}
+
+		// End points are repeated so the curve passes through the first and last control points
+		let p0 = self.points[if segment > 0 { segment - 1 } else { 0 }];
+		let p3 = self.points[(segment + 2).min(last)];
+
+		let catmull_rom = |a:f32, b:f32, c:f32, d:f32| -> f32 {
+			let t2 = t * t;
+			let t3 = t2 * t;
+			return 0.5 * ((2.0 * b) + (c - a) * t + (2.0 * a - 5.0 * b + 4.0 * c - d) * t2 + (3.0 * b - a - 3.0 * c + d) * t3);
+		};
+
+		return SplinePoint::new(
+			Vector2::new(
+				catmull_rom(p0.position.x, p1.position.x, p2.position.x, p3.position.x),
+				catmull_rom(p0.position.y, p1.position.y, p2.position.y, p3.position.y)
+			),
+			catmull_rom(p0.height, p1.height, p2.height, p3.height),
+			catmull_rom(p0.width, p1.width, p2.width, p3.width).max(0.0)
+		);
+	}
+
+	// Evaluates the curve roughly every `spacing` units
+	pub fn sample (&self, spacing:f32) -> Vec<SplinePoint> {
+		let mut samples:Vec<SplinePoint> = Vec::new();
+		if self.points.len() < 2 {
+			samples.extend(self.points.iter().cloned());
+			return samples;
+		}
+
+		let spacing = spacing.max(0.01);
+		for segment in 0..(self.points.len() - 1) {
+			let length = self.points[segment].position.distance_to(self.points[segment + 1].position);
+			let steps = ((length / spacing).ceil() as usize).max(1);
+			for s in 0..steps {
+				samples.push(self.point_at(segment, s as f32 / steps as f32));
+			}
+		}
+		samples.push(self.points[self.points.len() - 1]);
+		return samples;
+	}
+
+	// Returns the distance to the sampled curve along with the interpolated point closest to `point`
+	pub fn nearest (samples:&Vec<SplinePoint>, point:Vector2) -> Option<(f32, SplinePoint)> {
+		if samples.len() == 1 {
+			return Some((point.distance_to(samples[0].position), samples[0]));
+		}
+
+		let mut nearest:Option<(f32, SplinePoint)> = None;
+		for i in 1..samples.len() {
+			let a = samples[i - 1];
+			let b = samples[i];
+			let dir = b.position - a.position;
+			let length_squared = dir.square_length();
+			let t = if length_squared > 0.0 { ((point - a.position).dot(dir) / length_squared).clamp(0.0, 1.0) } else { 0.0 };
+			let closest = a.lerp(&b, t);
+			let dist = point.distance_to(closest.position);
+			if nearest.is_none() || dist < nearest.unwrap().0 {
+				nearest = Some((dist, closest));
+			}
+		}
+		return nearest;
+	}
+
+	pub fn to_dictionary (&self) -> Dictionary {
+		let dict = Dictionary::new();
+		let mut points:TypedArray<Vector3> = TypedArray::new();
+		let mut widths:TypedArray<f32> = TypedArray::new();
+		for point in self.points.iter() {
+			points.push(Vector3::new(point.position.x, point.height, point.position.y));
+			widths.push(point.width);
+		}
+		dict.insert("points", points);
+		dict.insert("widths", widths);
+		dict.insert("smooth", self.smooth);
+		dict.insert("carve_mode", self.carve_mode as i64);
+		dict.insert("embankment", self.embankment);
+		dict.insert("paint", self.paint_color.is_some());
+		dict.insert("paint_color", self.paint_color.unwrap_or(Color8::new(0, 0, 0, 0)).to_color());
+		return dict.into_shared();
+	}
+
+	pub fn from_dictionary (dict:&Dictionary) -> Self {
+		let mut spline = Spline::new();
+		spline.set_points(&dict.get("points").to_vector3_array(), &dict.get("widths").to_float32_array());
+		spline.smooth = dict.get("smooth").to_bool();
+		spline.carve_mode = dict.get("carve_mode").to_i64() as i32;
+		spline.embankment = dict.get("embankment").to_f64() as f32;
+		if dict.get("paint").to_bool() {
+			spline.paint_color = Some(Color8::from_color(dict.get("paint_color").to_color()));
+		}
+		return spline;
+	}
+
+	// Points are given as (x, height, z). Missing widths repeat the last one given
+	pub fn set_points (&mut self, points:&TypedArray<Vector3>, widths:&TypedArray<f32>) {
+		self.points.clear();
+		let mut width:f32 = 1.0;
+		for i in 0..points.len() {
+			if i < widths.len() {
+				width = widths.get(i);
+			}
+			let point = points.get(i);
+			self.points.push(SplinePoint::new(Vector2::new(point.x, point.z), point.y, width));
+		}
+	}
+}
@@ -1,4 +1,4 @@
-use gdnative::{prelude::{Vector3,Vector2,Color}, core_types::TypedArray};
+use gdnative::{prelude::{Vector3,Color}, core_types::TypedArray};
 
 #[derive(Debug,Copy,Clone,PartialEq)]
 pub struct Triangle {
@@ -11,50 +11,14 @@ impl Triangle {
     }
 }
 
-#[derive(Clone,Copy,Hash,Eq,PartialEq,Debug)]
-pub struct Vector2Key {
-    integral:[u32;2],
-    fractional:[u8;2]
-}
-
-impl Vector2Key {
-    pub fn from_vector2 (v:Vector2) -> Self {
-        Vector2Key::new(v.x, v.y)
-    }
-    pub fn from_vector3 (v:Vector3) -> Self {
-        Vector2Key::new(v.x, v.z)
-    }
-    pub fn new (x:f32, y:f32) -> Self {
-        let trunc:[f32;2] = [
-            x.trunc() as f32,
-            y.trunc() as f32
-        ];
-
-        Vector2Key {
-            integral: [
-                trunc[0] as u32,
-                trunc[1] as u32
-            ],
-            fractional: [
-                (x.fract() * 100.0).trunc() as u8,
-                (y.fract() * 100.0).trunc() as u8
-            ]
-        }
-    }
-
-    pub fn to_vector2 (&self) -> Vector2 {
-        Vector2::new(
-            self.integral[0] as f32 + (self.fractional[0] as f32) / 100.0,
-            self.integral[1] as f32 + (self.fractional[1] as f32) / 100.0
-        )
-    }
-}
-
-
+// Lowest and highest terrain heights allowed unless a map sets its own range
+pub const DEFAULT_MIN_HEIGHT:i32 = 0;
+pub const DEFAULT_MAX_HEIGHT:i32 = 1024;
 
+// Keys store the floor of each component so negative coordinates keep a fractional part in [0,1)
 #[derive(Clone,Copy,Hash,Eq,PartialEq,Debug)]
 pub struct Vector3Key {
-    integral:[u32;3],
+    integral:[i32;3],
     fractional:[u8;3]
 }
 
@@ -63,22 +27,22 @@ impl Vector3Key {
         Vector3Key::new(v.x, v.y, v.z)
     }
     pub fn new (x:f32, y:f32, z:f32) -> Self {
-        let trunc:[f32;3] = [
-            x.trunc() as f32,
-            y.trunc() as f32,
-            z.trunc() as f32
+        let floor:[f32;3] = [
+            x.floor(),
+            y.floor(),
+            z.floor()
         ];
 
         Vector3Key {
             integral: [
-                trunc[0] as u32,
-                trunc[1] as u32,
-                trunc[2] as u32
+                floor[0] as i32,
+                floor[1] as i32,
+                floor[2] as i32
             ],
             fractional: [
-                (x.fract() * 100.0).trunc() as u8,
-                (y.fract() * 100.0).trunc() as u8,
-                (z.fract() * 100.0).trunc() as u8
+                ((x - floor[0]) * 100.0).trunc() as u8,
+                ((y - floor[1]) * 100.0).trunc() as u8,
+                ((z - floor[2]) * 100.0).trunc() as u8
             ]
         }
     }
@@ -92,6 +56,47 @@ impl Vector3Key {
     }
 }
 
+// How a painted color combines with the color already on a cell. Hue, saturation and value replace
+// that HSV component, the channel modes leave alpha alone and Alpha changes nothing but alpha
+#[derive(Clone,Copy,PartialEq,Eq,Debug)]
+pub enum BlendMode {
+	Mix,
+	Add,
+	Multiply,
+	Subtract,
+	Screen,
+	Overlay,
+	SoftLight,
+	Lighten,
+	Darken,
+	Difference,
+	Hue,
+	Saturation,
+	Value,
+	Alpha,
+}
+
+impl BlendMode {
+	pub fn from_i32 (mode:i32) -> Self {
+		match mode {
+			1 => BlendMode::Add,
+			2 => BlendMode::Multiply,
+			3 => BlendMode::Subtract,
+			4 => BlendMode::Screen,
+			5 => BlendMode::Overlay,
+			6 => BlendMode::SoftLight,
+			7 => BlendMode::Lighten,
+			8 => BlendMode::Darken,
+			9 => BlendMode::Difference,
+			10 => BlendMode::Hue,
+			11 => BlendMode::Saturation,
+			12 => BlendMode::Value,
+			13 => BlendMode::Alpha,
+			_ => BlendMode::Mix
+		}
+	}
+}
+
 #[derive(Clone,Copy,PartialEq,Eq,Hash,Debug)]
 pub struct Color8 {
 	r: u8,
@@ -123,25 +128,102 @@ impl Color8 {
 		}
 	}
 
-	pub fn squared_similarity (&self, col:Color8) -> f32 {
-		let diff_r = 1.0 - (self.r as i32 - col.r as i32) as f32 / 255.0;
-		let diff_g = 1.0 - (self.g as i32 - col.g as i32) as f32 / 255.0;
-		let diff_b = 1.0 - (self.b as i32 - col.b as i32) as f32 / 255.0;
-		let diff_a = 1.0 - (self.a as i32 - col.a as i32) as f32 / 255.0;
-
-		return diff_r.min(diff_g).min(diff_b).min(diff_a);
+	// 1 for identical colors down to 0 for black against white. Uses the "redmean" weighted distance,
+	// which tracks perceived difference far better than comparing channels one by one
+	pub fn similarity (&self, col:Color8) -> f32 {
+		let mean_r = (self.r as f32 + col.r as f32) * 0.5;
+		let diff_r = self.r as f32 - col.r as f32;
+		let diff_g = self.g as f32 - col.g as f32;
+		let diff_b = self.b as f32 - col.b as f32;
+		let distance = ((2.0 + mean_r / 256.0) * diff_r * diff_r + 4.0 * diff_g * diff_g + (2.0 + (255.0 - mean_r) / 256.0) * diff_b * diff_b).sqrt();
+		let diff_a = (self.a as f32 - col.a as f32).abs() / 255.0;
+		return 1.0 - (distance / 764.83).max(diff_a).min(1.0);
 	}
 
-	pub fn blend (&self, color:Color8, strength:f32, blend_mode:i32) -> Color8 {
+	pub fn blend (&self, color:Color8, strength:f32, blend_mode:BlendMode) -> Color8 {
 		match blend_mode {
-			0 => self.mix(color, strength),
-			1 => self.add(color, strength),
-			2 => self.multiply(color, strength),
-			3 => self.subtract(color, strength),
-			_ => self.mix(color, strength)
+			BlendMode::Mix => self.mix(color, strength),
+			BlendMode::Add => self.add(color, strength),
+			BlendMode::Multiply => self.multiply(color, strength),
+			BlendMode::Subtract => self.subtract(color, strength),
+			BlendMode::Screen => self.blend_channels(color, strength, |a, b| 1.0 - (1.0 - a) * (1.0 - b)),
+			BlendMode::Overlay => self.blend_channels(color, strength, |a, b| {
+				return if a < 0.5 { 2.0 * a * b } else { 1.0 - 2.0 * (1.0 - a) * (1.0 - b) };
+			}),
+			BlendMode::SoftLight => self.blend_channels(color, strength, |a, b| (1.0 - 2.0 * b) * a * a + 2.0 * b * a),
+			BlendMode::Lighten => self.blend_channels(color, strength, |a, b| a.max(b)),
+			BlendMode::Darken => self.blend_channels(color, strength, |a, b| a.min(b)),
+			BlendMode::Difference => self.blend_channels(color, strength, |a, b| (a - b).abs()),
+			BlendMode::Hue | BlendMode::Saturation | BlendMode::Value => {
+				let (h, s, v) = self.to_hsv();
+				let (blend_h, blend_s, blend_v) = color.to_hsv();
+				let target = match blend_mode {
+					BlendMode::Hue => Color8::from_hsv(blend_h, s, v, self.a),
+					BlendMode::Saturation => Color8::from_hsv(h, blend_s, v, self.a),
+					_ => Color8::from_hsv(h, s, blend_v, self.a)
+				};
+				return self.mix(target, strength);
+			}
+			BlendMode::Alpha => {
+				let alpha = self.a as f32 + (color.a as f32 - self.a as f32) * strength;
+				return Color8::new(self.r, self.g, self.b, alpha.round().clamp(0.0, 255.0) as u8);
+			}
 		}
 	}
 
+	// Applies a per-channel blend function on [0,1] values to the color channels. Alpha is kept
+	fn blend_channels<F:Fn(f32, f32) -> f32> (&self, col:Color8, strength:f32, blend_fn:F) -> Color8 {
+		let channel = |a:u8, b:u8| -> u8 {
+			let a = a as f32 / 255.0;
+			let blended = blend_fn(a, b as f32 / 255.0);
+			return ((a + (blended - a) * strength) * 255.0).round().clamp(0.0, 255.0) as u8;
+		};
+		return Color8::new(channel(self.r, col.r), channel(self.g, col.g), channel(self.b, col.b), self.a);
+	}
+
+	// Hue in [0,1), saturation and value in [0,1]
+	pub fn to_hsv (&self) -> (f32, f32, f32) {
+		let r = self.r as f32 / 255.0;
+		let g = self.g as f32 / 255.0;
+		let b = self.b as f32 / 255.0;
+		let max = r.max(g).max(b);
+		let min = r.min(g).min(b);
+		let delta = max - min;
+
+		let mut h = if delta <= 0.0 {
+			0.0
+		}
+		else if max == r {
+			((g - b) / delta).rem_euclid(6.0)
+		}
+		else if max == g {
+			(b - r) / delta + 2.0
+		}
+		else {
+			(r - g) / delta + 4.0
+		};
+		h /= 6.0;
+		let s = if max > 0.0 { delta / max } else { 0.0 };
+		return (h, s, max);
+	}
+
+	pub fn from_hsv (h:f32, s:f32, v:f32, a:u8) -> Self {
+		let h = h.rem_euclid(1.0) * 6.0;
+		let c = v * s;
+		let x = c * (1.0 - (h.rem_euclid(2.0) - 1.0).abs());
+		let (r, g, b) = match h as i32 {
+			0 => (c, x, 0.0),
+			1 => (x, c, 0.0),
+			2 => (0.0, c, x),
+			3 => (0.0, x, c),
+			4 => (x, 0.0, c),
+			_ => (c, 0.0, x)
+		};
+		let m = v - c;
+		let to_u8 = |channel:f32| -> u8 { ((channel + m) * 255.0).round().clamp(0.0, 255.0) as u8 };
+		return Color8::new(to_u8(r), to_u8(g), to_u8(b), a);
+	}
+
 	pub fn mix (&self, col:Color8, strength:f32) -> Color8 {
 		let mix1:f32 = 1.0 - strength;
 		let mix2:f32 = strength;
@@ -187,6 +269,120 @@ impl Color8 {
 }
 
 
+// Color stops at offsets in [0,1], kept sorted by offset
+#[derive(Clone,Debug)]
+pub struct ColorGradient {
+	stops:Vec<(f32,Color8)>
+}
+
+impl ColorGradient {
+	// Offsets missing from the end are spread evenly, so colors alone make an even gradient
+	pub fn new (offsets:&TypedArray<f32>, colors:&TypedArray<Color>) -> Self {
+		let count = colors.len();
+		let mut stops:Vec<(f32,Color8)> = Vec::new();
+		for i in 0..count {
+			let offset = if i < offsets.len() {
+				offsets.get(i)
+			}
+			else if count > 1 {
+				i as f32 / (count - 1) as f32
+			}
+			else {
+				0.0
+			};
+			stops.push((offset.clamp(0.0, 1.0), Color8::from_color(colors.get(i))));
+		}
+		stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+		ColorGradient { stops: stops }
+	}
+
+	pub fn is_empty (&self) -> bool {
+		return self.stops.len() == 0;
+	}
+
+	pub fn sample (&self, t:f32) -> Color8 {
+		let first = self.stops[0];
+		if t <= first.0 {
+			return first.1;
+		}
+		for i in 1..self.stops.len() {
+			let (offset, color) = self.stops[i];
+			if t <= offset {
+				let (prev_offset, prev_color) = self.stops[i - 1];
+				let span = offset - prev_offset;
+				let local_t = if span > 0.0 { (t - prev_offset) / span } else { 1.0 };
+				return prev_color.mix(color, local_t);
+			}
+		}
+		return self.stops[self.stops.len() - 1].1;
+	}
+}
+
+
+// A copied block of terrain. Weights below 1 come from a feathered or partial selection
+#[derive(Clone,Debug)]
+pub struct TerrainRegion {
+	pub size_x:usize,
+	pub size_z:usize,
+	pub heights:Vec<i32>,
+	pub colors:Vec<Color8>,
+	pub weights:Vec<f32>,
+}
+
+impl TerrainRegion {
+	pub fn new (size_x:usize, size_z:usize) -> Self {
+		TerrainRegion {
+			size_x: size_x,
+			size_z: size_z,
+			heights: vec![0; size_x * size_z],
+			colors: vec![Color8::new(0, 0, 0, 0); size_x * size_z],
+			weights: vec![0.0; size_x * size_z],
+		}
+	}
+
+	pub fn min_height (&self) -> i32 {
+		let mut min_height:Option<i32> = None;
+		for i in 0..self.heights.len() {
+			if self.weights[i] <= 0.0 { continue; }
+			min_height = Some(min_height.map_or(self.heights[i], |h| h.min(self.heights[i])));
+		}
+		return min_height.unwrap_or(0);
+	}
+
+	// Mirrors first, then rotates clockwise by 90 degree steps
+	pub fn transformed (&self, rotation:i32, mirror_x:bool, mirror_z:bool) -> TerrainRegion {
+		let mut region = self.clone();
+		if mirror_x || mirror_z {
+			for z in 0..self.size_z {
+				for x in 0..self.size_x {
+					let src_x = if mirror_x { self.size_x - 1 - x } else { x };
+					let src_z = if mirror_z { self.size_z - 1 - z } else { z };
+					region.copy_cell(self, src_z * self.size_x + src_x, z * self.size_x + x);
+				}
+			}
+		}
+
+		for _turn in 0..rotation.rem_euclid(4) {
+			let source = region.clone();
+			region = TerrainRegion::new(source.size_z, source.size_x);
+			for z in 0..source.size_z {
+				for x in 0..source.size_x {
+					let new_x = source.size_z - 1 - z;
+					let new_z = x;
+					region.copy_cell(&source, z * source.size_x + x, new_z * region.size_x + new_x);
+				}
+			}
+		}
+		return region;
+	}
+
+	fn copy_cell (&mut self, source:&TerrainRegion, src_index:usize, dst_index:usize) {
+		self.heights[dst_index] = source.heights[src_index];
+		self.colors[dst_index] = source.colors[src_index];
+		self.weights[dst_index] = source.weights[src_index];
+	}
+}
+
 pub struct QuadTree {
 	size:usize,
     nodes:Vec<Quad>,
@@ -848,3 +1044,58 @@ impl OctNode {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const BASE:Color8 = Color8 { r: 200, g: 100, b: 50, a: 255 };
+	const LAYER:Color8 = Color8 { r: 100, g: 150, b: 240, a: 128 };
+
+	fn blended (mode:BlendMode, strength:f32) -> Color8 {
+		return BASE.blend(LAYER, strength, mode);
+	}
+
+	#[test]
+	fn channel_blend_modes () {
+		assert_eq!(blended(BlendMode::Screen, 1.0), Color8::new(222, 191, 243, 255));
+		assert_eq!(blended(BlendMode::Overlay, 1.0), Color8::new(188, 118, 94, 255));
+		assert_eq!(blended(BlendMode::SoftLight, 1.0), Color8::new(191, 111, 85, 255));
+		assert_eq!(blended(BlendMode::Difference, 1.0), Color8::new(100, 50, 190, 255));
+		assert_eq!(blended(BlendMode::Lighten, 1.0), Color8::new(200, 150, 240, 255));
+		assert_eq!(blended(BlendMode::Darken, 1.0), Color8::new(100, 100, 50, 255));
+	}
+
+	#[test]
+	fn blend_strength_fades_toward_base () {
+		assert_eq!(blended(BlendMode::Screen, 0.5), Color8::new(211, 146, 146, 255));
+		assert_eq!(blended(BlendMode::Darken, 0.5), Color8::new(150, 100, 50, 255));
+		assert_eq!(blended(BlendMode::Screen, 0.0), BASE);
+	}
+
+	#[test]
+	fn hsv_blend_modes () {
+		assert_eq!(blended(BlendMode::Hue, 1.0), Color8::new(50, 104, 200, 255));
+		assert_eq!(blended(BlendMode::Saturation, 1.0), Color8::new(200, 122, 83, 255));
+		assert_eq!(blended(BlendMode::Value, 1.0), Color8::new(240, 120, 60, 255));
+	}
+
+	#[test]
+	fn alpha_blend_only_changes_alpha () {
+		assert_eq!(blended(BlendMode::Alpha, 1.0), Color8::new(200, 100, 50, 128));
+		assert_eq!(blended(BlendMode::Alpha, 0.25), Color8::new(200, 100, 50, 223));
+	}
+
+	#[test]
+	fn hsv_round_trip () {
+		for r in (0..=255).step_by(15) {
+			for g in (0..=255).step_by(15) {
+				for b in (0..=255).step_by(15) {
+					let color = Color8::new(r as u8, g as u8, b as u8, 77);
+					let (h, s, v) = color.to_hsv();
+					assert_eq!(Color8::from_hsv(h, s, v, color.a), color);
+				}
+			}
+		}
+	}
+}
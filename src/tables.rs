@@ -15,7 +15,7 @@ pub const POINTS: [Vector3; 8] = [
 ];
 
 
-/*pub const TRANSITION_POINTS:[Vector3;13] =[
+pub const TRANSITION_POINTS:[Vector3;13] =[
     Vector3::new(0.0, 0.0, 0.0), // 0
     Vector3::new(0.5, 0.0, 0.0), // 1
     Vector3::new(1.0, 0.0, 0.0), // 2
@@ -29,7 +29,7 @@ pub const POINTS: [Vector3; 8] = [
     Vector3::new(1.0, 1.0, 0.0), // 10
     Vector3::new(0.0, 1.0, 1.0), // 11
     Vector3::new(1.0, 1.0, 1.0), // 12
-];*/
+];
 
 
 pub const EDGE_TABLE: [u32; 256] = [
@@ -55,7 +55,8 @@ pub const EDGE_TABLE: [u32; 256] = [
     0x70c, 0x605, 0x50f, 0x406, 0x30a, 0x203, 0x109, 0x0,
 ];
 
-/*pub const TRANSITION_EDGE_TABLE:[i32;512] = [
+// Equivalence class of each transition cell case, the high bit marks cases whose triangles are inverted
+pub const TRANSITION_EDGE_TABLE:[i32;512] = [
 	0x00, 0x01, 0x02, 0x84, 0x01, 0x05, 0x04, 0x04, 0x02, 0x87, 0x09, 0x8C, 0x84, 0x0B, 0x05, 0x05,
 	0x01, 0x08, 0x07, 0x8D, 0x05, 0x0F, 0x8B, 0x0B, 0x04, 0x0D, 0x0C, 0x1C, 0x04, 0x8B, 0x85, 0x85,
 	0x02, 0x07, 0x09, 0x8C, 0x87, 0x10, 0x0C, 0x0C, 0x09, 0x12, 0x15, 0x9A, 0x8C, 0x19, 0x90, 0x10,
@@ -88,7 +89,7 @@ pub const EDGE_TABLE: [u32; 256] = [
 	0x17, 0xAB, 0x2D, 0xA6, 0xAE, 0x2A, 0x28, 0x25, 0x8C, 0x8C, 0x90, 0x07, 0x0C, 0x89, 0x87, 0x82,
 	0x05, 0x05, 0x0B, 0x84, 0x2F, 0x26, 0x35, 0x84, 0x8B, 0x0B, 0x8F, 0x85, 0xB5, 0x87, 0x34, 0x81,
 	0x85, 0x85, 0x8B, 0x04, 0xA6, 0x25, 0x07, 0x82, 0x84, 0x84, 0x85, 0x81, 0x04, 0x82, 0x81, 0x80
-];*/
+];
 
 //0000 1001 1110
 
@@ -367,7 +368,8 @@ pub const EDGE_TO_POINTS:[u8;24] = [
     3,7];
 
 
-/*pub const TRANSITION_TRI_DATA:[[i8;36];56] = [
+// Triangles of each class as indices into the case's entry in TRANSITION_VERTEX_DATA
+pub const TRANSITION_TRI_DATA:[[i8;36];56] = [
 	[-1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
     [0, 1, 3, 1, 2, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
     [0, 1, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
@@ -429,6 +431,7 @@ pub const EDGE_TO_POINTS:[u8;24] = [
 
 
 
+// Low byte holds the two TRANSITION_POINTS of the edge each vertex lies on
 pub const TRANSITION_VERTEX_DATA:[[i32;12]; 512] = [
     [-1,-1,-1,-1,-1,-1,-1,-1,-1,-1,-1,-1],
     [0x2301, 0x1503, 0x199B, 0x289A,-1,-1,-1,-1,-1,-1,-1,-1],
@@ -942,4 +945,4 @@ pub const TRANSITION_VERTEX_DATA:[[i32;12]; 512] = [
     [0x2301, 0x2412, 0x4514,-1,-1,-1,-1,-1,-1,-1,-1,-1],
     [0x2301, 0x1503, 0x199B, 0x289A,-1,-1,-1,-1,-1,-1,-1,-1],
     [-1,-1,-1,-1,-1,-1,-1,-1,-1,-1,-1,-1],
-];*/
\ No newline at end of file
+];
@@ -6,7 +6,9 @@ use gdnative::prelude::*;
 use gdnative::api::Resource;
 use gdnative::api::SurfaceTool;
 use gdnative::api::MeshInstance;
-use crate::marching_cubes::generate_chunk_mesh_from_height_array;
+use crate::marching_cubes::{generate_chunk_mesh_from_height_array, border_faces, add_border_walls};
+use crate::normals::*;
+use crate::uv::*;
 use std::collections::HashMap;
 use gdnative::nativescript::property::*;
 
@@ -18,13 +20,24 @@ use gdnative::nativescript::property::*;
 pub struct Terrain {
 	step_height:f32,
 	heights:Vec<i32>,
-	map_size:usize,
+	map_width:usize,
+	map_depth:usize,
 	#[property(default=4)]
-	num_chunks:i64,
+	num_chunks_x:i64,
+	#[property(default=4)]
+	num_chunks_z:i64,
 	#[property(default=16)]
 	chunk_size:i64,
-	locked_height:i32,
+	locked_height:Option<i32>,
 	is_height_locked:bool,
+	min_height:i32,
+	max_height:i32,
+	crease_angle:f32,
+	generate_uvs:bool,
+	uv_scale:f32,
+	border_walls:bool,
+	border_cap:bool,
+	base_height:i64,
 
 	undo_stack:Vec<HashMap<usize,i32>>,
 	redo_stack:Vec<HashMap<usize,i32>>,
@@ -43,9 +56,16 @@ impl Terrain {
 
 	fn register_properties(builder: &ClassBuilder<Terrain>) {
 		builder
-            .add_property::<i64>("num_chunks")
-            .with_getter(move |my_node: &Terrain, _owner: TRef<Resource>| my_node.num_chunks)
-            .with_setter(move |my_node: &mut Terrain, _owner: TRef<Resource>, new_value| my_node.num_chunks = new_value)
+            .add_property::<i64>("num_chunks_x")
+            .with_getter(move |my_node: &Terrain, _owner: TRef<Resource>| my_node.num_chunks_x)
+            .with_setter(move |my_node: &mut Terrain, _owner: TRef<Resource>, new_value| my_node.num_chunks_x = new_value)
+            .with_default(4)
+            .with_hint(IntHint::Range(RangeHint::new(1, 64).with_step(1)))
+            .done();
+		builder
+            .add_property::<i64>("num_chunks_z")
+            .with_getter(move |my_node: &Terrain, _owner: TRef<Resource>| my_node.num_chunks_z)
+            .with_setter(move |my_node: &mut Terrain, _owner: TRef<Resource>, new_value| my_node.num_chunks_z = new_value)
             .with_default(4)
             .with_hint(IntHint::Range(RangeHint::new(1, 64).with_step(1)))
             .done();
@@ -55,6 +75,44 @@ impl Terrain {
             .with_setter(move |my_node: &mut Terrain, _owner: TRef<Resource>, new_value| my_node.chunk_size = new_value)
             .with_default(16)
             .with_hint(IntHint::Range(RangeHint::new(4, 64).with_step(1)))
+            .done();
+		builder
+            .add_property::<f32>("crease_angle")
+            .with_getter(move |my_node: &Terrain, _owner: TRef<Resource>| my_node.crease_angle)
+            .with_setter(move |my_node: &mut Terrain, _owner: TRef<Resource>, new_value| my_node.crease_angle = new_value)
+            .with_default(DEFAULT_CREASE_ANGLE)
+            .with_hint(FloatHint::Range(RangeHint::new(0.0, 180.0).with_step(1.0)))
+            .done();
+		// UV holds world space texture coordinates and UV2 the top or wall classification
+		builder
+            .add_property::<bool>("generate_uvs")
+            .with_getter(move |my_node: &Terrain, _owner: TRef<Resource>| my_node.generate_uvs)
+            .with_setter(move |my_node: &mut Terrain, _owner: TRef<Resource>, new_value| my_node.generate_uvs = new_value)
+            .with_default(false)
+            .done();
+		builder
+            .add_property::<f32>("uv_scale")
+            .with_getter(move |my_node: &Terrain, _owner: TRef<Resource>| my_node.uv_scale)
+            .with_setter(move |my_node: &mut Terrain, _owner: TRef<Resource>, new_value| my_node.uv_scale = new_value)
+            .with_default(1.0)
+            .done();
+		builder
+            .add_property::<bool>("border_walls")
+            .with_getter(move |my_node: &Terrain, _owner: TRef<Resource>| my_node.border_walls)
+            .with_setter(move |my_node: &mut Terrain, _owner: TRef<Resource>, new_value| my_node.border_walls = new_value)
+            .with_default(false)
+            .done();
+		builder
+            .add_property::<bool>("border_cap")
+            .with_getter(move |my_node: &Terrain, _owner: TRef<Resource>| my_node.border_cap)
+            .with_setter(move |my_node: &mut Terrain, _owner: TRef<Resource>, new_value| my_node.border_cap = new_value)
+            .with_default(false)
+            .done();
+		builder
+            .add_property::<i64>("base_height")
+            .with_getter(move |my_node: &Terrain, _owner: TRef<Resource>| my_node.base_height)
+            .with_setter(move |my_node: &mut Terrain, _owner: TRef<Resource>, new_value| my_node.base_height = new_value)
+            .with_default(DEFAULT_MIN_HEIGHT as i64)
             .done();
 	}
 
@@ -62,11 +120,21 @@ impl Terrain {
         Terrain {
 			step_height: 1.0,
 			heights: Vec::new(),
-			map_size: 0,
-			num_chunks: 0,
+			map_width: 0,
+			map_depth: 0,
+			num_chunks_x: 0,
+			num_chunks_z: 0,
 			chunk_size: 0,
-			locked_height: -1,
+			locked_height: None,
 			is_height_locked:false,
+			min_height: DEFAULT_MIN_HEIGHT,
+			max_height: DEFAULT_MAX_HEIGHT,
+			crease_angle: DEFAULT_CREASE_ANGLE,
+			generate_uvs: false,
+			uv_scale: 1.0,
+			border_walls: false,
+			border_cap: false,
+			base_height: DEFAULT_MIN_HEIGHT as i64,
 
 			undo_stack:Vec::new(),
 			redo_stack:Vec::new(),
@@ -81,12 +149,13 @@ impl Terrain {
     }
 
 	#[export]
-	fn init_params (&mut self, _owner:&Resource, _num_chunks:i64, _chunk_size:i64, _step_height:f32) {
-		self.num_chunks = _num_chunks.max(1);
+	fn init_params (&mut self, _owner:&Resource, _num_chunks_x:i64, _num_chunks_z:i64, _chunk_size:i64, _step_height:f32) {
+		self.num_chunks_x = _num_chunks_x.max(1);
+		self.num_chunks_z = _num_chunks_z.max(1);
 		self.chunk_size = _chunk_size.max(1);
-		self.map_size = self.num_chunks as usize * self.chunk_size as usize;
-		let total_size = _num_chunks * _chunk_size;
-		self.heights = vec![0; (total_size * total_size) as usize];
+		self.map_width = self.num_chunks_x as usize * self.chunk_size as usize;
+		self.map_depth = self.num_chunks_z as usize * self.chunk_size as usize;
+		self.heights = vec![0; self.map_width * self.map_depth];
 		self.step_height = _step_height;
 	}
 
@@ -119,9 +188,12 @@ impl Terrain {
 		file.open(path, File::WRITE).unwrap();
 		let variables= Dictionary::new();
 		variables.insert("heights".to_variant(), self.heights.clone());
-		variables.insert("num_chunks".to_variant(), self.num_chunks);
+		variables.insert("num_chunks_x".to_variant(), self.num_chunks_x);
+		variables.insert("num_chunks_z".to_variant(), self.num_chunks_z);
 		variables.insert("chunk_size".to_variant(), self.chunk_size);
 		variables.insert("step_height".to_variant(), self.step_height);
+		variables.insert("min_height".to_variant(), self.min_height);
+		variables.insert("max_height".to_variant(), self.max_height);
 		variables.insert("settings".to_variant(), settings);
 		file.store_var(variables, true);
 		file.close();
@@ -139,8 +211,8 @@ impl Terrain {
 		
 
 		for chunk_id in 0..self.chunks.len() {
-			let offset_x = self.chunk_size as i32 * (chunk_id as i32 % self.num_chunks as i32);
-			let offset_z = self.chunk_size as i32 * (chunk_id as i32 / self.num_chunks as i32);
+			let offset_x = self.chunk_size as i32 * (chunk_id as i32 % self.num_chunks_x as i32);
+			let offset_z = self.chunk_size as i32 * (chunk_id as i32 / self.num_chunks_x as i32);
 			for i in (0..self.chunk_indices[chunk_id].len()).step_by(3) {
 				for n in 0..3 {
 					let idx = self.chunk_indices[chunk_id][i + n];
@@ -162,18 +234,38 @@ impl Terrain {
 		}
 
 
+		for vertex in vertex_list.iter_mut() {
+			vertex.y *= self.step_height;
+		}
+		let (vertex_list, normal_list, index_list) = crease_normals(&vertex_list, &index_list, self.crease_angle);
+
 		let file:Ref<File,Unique> = File::new();
 		file.open(path, File::WRITE).unwrap();
 		file.store_line("o terrain");
 
 		for vertex in vertex_list.iter() {
-			file.store_line(format!("v {} {} {}", vertex.x, vertex.y * self.step_height, vertex.z));
+			file.store_line(format!("v {} {} {}", vertex.x, vertex.y, vertex.z));
 		}
 
-		file.store_line("s 1");
+		// OBJ has a single texture coordinate channel, so only the world space UVs are written
+		if self.generate_uvs {
+			let (uvs, _) = generate_uvs(&vertex_list, &normal_list, Vector3::new(0.0, 0.0, 0.0), self.uv_scale);
+			for uv in uvs.iter() {
+				file.store_line(format!("vt {} {}", uv.x, uv.y));
+			}
+		}
+
+		for normal in normal_list.iter() {
+			file.store_line(format!("vn {} {} {}", normal.x, normal.y, normal.z));
+		}
 
 		for i in (0..index_list.len()).step_by(3) {
-			file.store_line(format!("f {} {} {}", index_list[i + 2] + 1, index_list[i + 1] + 1, index_list[i] + 1));
+			let (a, b, c) = (index_list[i + 2] + 1, index_list[i + 1] + 1, index_list[i] + 1);
+			if self.generate_uvs {
+				file.store_line(format!("f {}/{}/{} {}/{}/{} {}/{}/{}", a, a, a, b, b, b, c, c, c));
+			} else {
+				file.store_line(format!("f {}//{} {}//{} {}//{}", a, a, b, b, c, c));
+			}
 		}
 		file.close();
 
@@ -199,12 +291,11 @@ impl Terrain {
 	#[export]
 	fn get_heights_for_collision (&mut self, _owner:&Resource) -> TypedArray<f32> {
 		let mut v:Vec<f32> = Vec::new();
-		let map_sized_plus = self.map_size + 1;
-		for z in 0..map_sized_plus {
-			let z_min = z.min(self.map_size - 1);
-			for x in 0..map_sized_plus {
-				let x_min = x.min(self.map_size - 1);
-				let y = self.heights[(z_min * self.map_size + x_min) as usize];
+		for z in 0..(self.map_depth + 1) {
+			let z_min = z.min(self.map_depth - 1);
+			for x in 0..(self.map_width + 1) {
+				let x_min = x.min(self.map_width - 1);
+				let y = self.heights[(z_min * self.map_width + x_min) as usize];
 				v.push(y as f32 * self.step_height + 0.5 * self.step_height);
 			}
 		}
@@ -228,17 +319,34 @@ impl Terrain {
 	}
 
 	#[export]
-	pub fn get_locked_height (&self, _owner:&Resource) -> i32 {
-		return self.locked_height;
+	pub fn get_locked_height (&self, _owner:&Resource) -> Variant {
+		return match self.locked_height {
+			Some(height) => height.to_variant(),
+			None => Variant::new()
+		};
 	}
 	#[export]
 	pub fn set_locked_height (&mut self, _owner:&Resource, value:i32) {
-		self.locked_height = value;
+		self.locked_height = Some(value);
+	}
+
+	#[export]
+	pub fn set_height_range (&mut self, _owner:&Resource, min_height:i32, max_height:i32) {
+		self.min_height = min_height.min(max_height);
+		self.max_height = min_height.max(max_height);
+	}
+	#[export]
+	pub fn get_min_height (&self, _owner:&Resource) -> i32 {
+		return self.min_height;
+	}
+	#[export]
+	pub fn get_max_height (&self, _owner:&Resource) -> i32 {
+		return self.max_height;
 	}
 
 	#[export]
 	pub fn in_bounds (&mut self, _owner:&Resource, global_x:i32, global_z:i32) -> bool {
-		return global_x > -1 && global_z > -1 && global_x < self.map_size as i32 && global_z < self.map_size as i32;
+		return global_x > -1 && global_z > -1 && global_x < self.map_width as i32 && global_z < self.map_depth as i32;
 	}
 
 	#[export]
@@ -252,7 +360,7 @@ impl Terrain {
 
 	#[export]
 	pub fn end_stroke(&mut self, _owner:&Resource) {
-		self.locked_height = -1;
+		self.locked_height = None;
 		self.is_drawing = false;
 		if self.edited_positions.len() > 0 {
 			self.undo_stack.push(self.edited_positions.clone());
@@ -268,7 +376,7 @@ impl Terrain {
 		let mut positions:Vec<(i32,i32,i32)> = Vec::new();
 
 		let mut add_position = |gx:i32, gz:i32| {
-			if gx > -1 && gz > -1 && gx < self.map_size as i32 && gz < self.map_size as i32 {
+			if gx > -1 && gz > -1 && gx < self.map_width as i32 && gz < self.map_depth as i32 {
 				positions.push((gx as i32, self.height(gx as i32, gz as i32), gz as i32));
 			}
 		};
@@ -348,12 +456,12 @@ impl Terrain {
 
 						let new_height = *y - sign;
 						if *y != new_height {
-							let index = *z as usize * self.map_size + *x as usize;
+							let index = *z as usize * self.map_width + *x as usize;
 							if !self.edited_positions.contains_key(&index) {
 								self.edited_positions.insert(index, *y);
 							}
 							self.heights[index as usize] = new_height;
-							let chunk_id:i32 = ((z / self.chunk_size as i32) * self.num_chunks as i32 + (x / self.chunk_size as i32)) as i32;
+							let chunk_id:i32 = ((z / self.chunk_size as i32) * self.num_chunks_x as i32 + (x / self.chunk_size as i32)) as i32;
 							self.check_seam_condition(*x as i32, *z as i32);  
 							dirtied_chunks_set.insert(chunk_id);
 						}
@@ -382,32 +490,30 @@ impl Terrain {
 	}
 
 	fn draw_height_at (&mut self, global_x:i32, global_z:i32, draw_mode:i32, brush_height:i32, button_index:i32) -> i32 {
-		if global_x < 0 || global_z < 0 || global_x >= self.map_size as i32 || global_z >= self.map_size as i32 {
+		if global_x < 0 || global_z < 0 || global_x >= self.map_width as i32 || global_z >= self.map_depth as i32 {
 			return -1;
 		}
 
-		let i:usize = global_z as usize * self.map_size + global_x as usize;
+		let i:usize = global_z as usize * self.map_width + global_x as usize;
 		if self.edited_positions.contains_key(&i) {
 			return -1;
 		}
 
 		let current_height = self.height(global_x as i32, global_z as i32);
-		if self.locked_height == -1 {
-			self.locked_height = current_height;
-		}
+		let locked_height = *self.locked_height.get_or_insert(current_height);
 		let mut new_height = current_height;
 
 		if (draw_mode == 0 || draw_mode == 1) &&
-			((current_height == self.locked_height && self.is_height_locked) || !self.is_height_locked) {
+			((current_height == locked_height && self.is_height_locked) || !self.is_height_locked) {
 				if (draw_mode == 0 && button_index == 1) || (draw_mode == 1 && button_index == 2) {
 					new_height = current_height + brush_height as i32;
 				}
 				else if (draw_mode == 1 && button_index == 1) || (draw_mode == 0 && button_index == 2) {
-					new_height = (current_height - brush_height as i32).max(0);
+					new_height = current_height - brush_height as i32;
 				}
 		}
 		else if draw_mode == 2 {
-			if self.is_height_locked && current_height == self.locked_height {
+			if self.is_height_locked && current_height == locked_height {
 				new_height = current_height + brush_height as i32;
 			}
 			else if !self.is_height_locked {
@@ -416,22 +522,24 @@ impl Terrain {
 		}
 
 		else if draw_mode == 3 {
-			if self.is_height_locked && current_height < self.locked_height + brush_height as i32 {
-				new_height = self.locked_height + brush_height as i32;
+			if self.is_height_locked && current_height < locked_height + brush_height as i32 {
+				new_height = locked_height + brush_height as i32;
 			}
 			else if !self.is_height_locked && current_height < brush_height as i32 {
 				new_height = brush_height as i32;
 			}
 		}
 		else if draw_mode == 4 {
-			if self.is_height_locked && current_height > self.locked_height + brush_height as i32 {
-				new_height = self.locked_height + brush_height as i32;
+			if self.is_height_locked && current_height > locked_height + brush_height as i32 {
+				new_height = locked_height + brush_height as i32;
 			}
 			else if !self.is_height_locked && current_height > brush_height as i32 {
 				new_height = brush_height as i32;
 			}
 		}
 
+		new_height = new_height.clamp(self.min_height, self.max_height);
+
 		if new_height != current_height {
 			self.edited_positions.insert(i, current_height);
 			self.set_height_at(global_x as i32, global_z as i32, new_height);
@@ -441,7 +549,7 @@ impl Terrain {
 
 			self.check_seam_condition(global_x, global_z);
 			
-			return (chunk_row * self.num_chunks as i32 + chunk_col) as i32;
+			return (chunk_row * self.num_chunks_x as i32 + chunk_col) as i32;
 		}
 
 		return -1;
@@ -452,118 +560,130 @@ impl Terrain {
 		let global_z = global_z as usize;
 
 		let chunk_size:usize = self.chunk_size as usize;
-		let num_chunks = self.num_chunks as usize;
+		let num_chunks_x = self.num_chunks_x as usize;
 
 		let chunk_col = global_x as usize / chunk_size;
 		let chunk_row = global_z as usize / chunk_size;
 
 		let x_border_min = global_x % chunk_size == 0 && chunk_col > 0;
-		let x_border_max = global_x % chunk_size == 1 && chunk_col < num_chunks - 1;
+		let x_border_max = global_x % chunk_size == 1 && chunk_col < num_chunks_x - 1;
 
 		let z_border_min = global_z % chunk_size == 0 && chunk_row > 0;
-		let z_border_max = global_z % chunk_size == 1  && chunk_row < num_chunks - 1;
+		let z_border_max = global_z % chunk_size == 1  && chunk_row < self.num_chunks_z as usize - 1;
 
 
 		if x_border_min {
 			if z_border_min {
-				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks + chunk_col - 1);
-				self.chunks_at_seam.insert(chunk_row * num_chunks  + chunk_col - 1);
-				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks  + chunk_col);
+				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks_x + chunk_col - 1);
+				self.chunks_at_seam.insert(chunk_row * num_chunks_x  + chunk_col - 1);
+				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks_x  + chunk_col);
 			}
 			else if z_border_max {
-				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks + chunk_col - 1);
-				self.chunks_at_seam.insert(chunk_row * num_chunks + chunk_col - 1);
-				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks + chunk_col);
+				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks_x + chunk_col - 1);
+				self.chunks_at_seam.insert(chunk_row * num_chunks_x + chunk_col - 1);
+				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks_x + chunk_col);
 			}
 			else {
-				self.chunks_at_seam.insert(chunk_row * num_chunks + chunk_col - 1);
+				self.chunks_at_seam.insert(chunk_row * num_chunks_x + chunk_col - 1);
 			}
 		}
 		else if x_border_max {
 			if z_border_min {
-				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks + chunk_col + 1);
-				self.chunks_at_seam.insert(chunk_row * num_chunks + chunk_col + 1);
-				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks + chunk_col);
+				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks_x + chunk_col + 1);
+				self.chunks_at_seam.insert(chunk_row * num_chunks_x + chunk_col + 1);
+				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks_x + chunk_col);
 			}
 			else if z_border_max {
-				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks + chunk_col + 1);
-				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks + chunk_col);
-				self.chunks_at_seam.insert(chunk_row * num_chunks + chunk_col + 1);
+				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks_x + chunk_col + 1);
+				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks_x + chunk_col);
+				self.chunks_at_seam.insert(chunk_row * num_chunks_x + chunk_col + 1);
 			}
 			else {
-				self.chunks_at_seam.insert(chunk_row * num_chunks + chunk_col + 1);
+				self.chunks_at_seam.insert(chunk_row * num_chunks_x + chunk_col + 1);
 			}
 		}
 		else {
 			if z_border_min {
-				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks + chunk_col);
+				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks_x + chunk_col);
 			}
 			else if z_border_max {
-				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks + chunk_col);
+				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks_x + chunk_col);
 			}
 		}
 	}
 
 	fn height (&self, global_x:i32, global_z:i32) -> i32 {
-		let idx:i32 = global_z * self.map_size as i32 + global_x;
+		let idx:i32 = global_z * self.map_width as i32 + global_x;
 		return self.heights[idx as usize];
 	}
 
 	#[export]
 	fn get_height (&self, _owner:&Resource, global_x:i32, global_z:i32) -> i32 {
-		let x_clamp = (global_x.max(0) as i32).min(self.map_size as i32 - 1);
-		let z_clamp = (global_z.max(0) as i32).min(self.map_size as i32 - 1);
-		let idx:i32 =  z_clamp * self.map_size as i32 + x_clamp;
+		let x_clamp = (global_x.max(0) as i32).min(self.map_width as i32 - 1);
+		let z_clamp = (global_z.max(0) as i32).min(self.map_depth as i32 - 1);
+		let idx:i32 =  z_clamp * self.map_width as i32 + x_clamp;
 		return self.heights[idx as usize];
 	}
 
+	// x_move and z_move anchor the old map when the size changes: 0 at the start, 1 centred, 2 at the end
 	#[export]
-	fn resize_terrain (&mut self, _owner:&Resource, new_chunk_size:i32, new_num_chunks:i32, x_move:i32, z_move:i32) {
-		let new_map_size:i32 = new_chunk_size * new_num_chunks;
-		let diff:i32 = new_map_size as i32 - self.map_size as i32;
-		if diff == 0 { return; }
-
-		let half_size:i32 = ((diff as f32) / 2.0).trunc() as i32;
-
-		let offset_x:i32 = match x_move {
-			0 => 0,
-			1 => half_size,
-			2 => diff,
-			_ => 0
-		};
-		let offset_z:i32 = match z_move {
-			0 => 0,
-			1 => half_size,
-			2 => diff,
-			_ => 0
+	fn resize_terrain (&mut self, _owner:&Resource, new_chunk_size:i32, new_num_chunks_x:i32, new_num_chunks_z:i32, x_move:i32, z_move:i32) {
+		let new_chunk_size = new_chunk_size.max(1);
+		let new_num_chunks_x = new_num_chunks_x.max(1);
+		let new_num_chunks_z = new_num_chunks_z.max(1);
+		let diff_x:i32 = new_chunk_size * new_num_chunks_x - self.map_width as i32;
+		let diff_z:i32 = new_chunk_size * new_num_chunks_z - self.map_depth as i32;
+
+		let anchor_offset = |diff:i32, anchor:i32| -> i32 {
+			match anchor {
+				1 => ((diff as f32) / 2.0).trunc() as i32,
+				2 => diff,
+				_ => 0
+			}
 		};
 
-		let mut new_heights:Vec<i32> = vec![0; (new_map_size * new_map_size) as usize];
+		self.resize_cells(new_chunk_size, new_num_chunks_x, new_num_chunks_z, anchor_offset(diff_x, x_move), anchor_offset(diff_z, z_move));
+	}
 
-		if diff > 0 {
-			for z in 0..self.map_size {
-				for x in 0..self.map_size {
-					let idx = z * self.map_size + x;
-					let current_height = self.heights[idx as usize];
-					let new_idx = (z as i32 + offset_z) * new_map_size as i32 + x as i32 + offset_x as i32;
-					new_heights[new_idx as usize] = current_height;
-				}
-			}
+	// Adds chunks to each side of the map. Negative amounts crop chunks away instead
+	#[export]
+	fn pad_terrain (&mut self, _owner:&Resource, left:i32, right:i32, top:i32, bottom:i32) -> bool {
+		let new_num_chunks_x = self.num_chunks_x as i32 + left + right;
+		let new_num_chunks_z = self.num_chunks_z as i32 + top + bottom;
+		if new_num_chunks_x < 1 || new_num_chunks_z < 1 {
+			return false;
+		}
+		let chunk_size = self.chunk_size as i32;
+		self.resize_cells(chunk_size, new_num_chunks_x, new_num_chunks_z, left * chunk_size, top * chunk_size);
+		return true;
+	}
+
+	// Rebuilds the height array for a new chunk grid. Old cell (x, z) moves to (x + offset_x, z + offset_z),
+	// cells pushed outside are dropped and new cells start flat
+	fn resize_cells (&mut self, new_chunk_size:i32, new_num_chunks_x:i32, new_num_chunks_z:i32, offset_x:i32, offset_z:i32) {
+		let new_map_width:i32 = new_chunk_size * new_num_chunks_x;
+		let new_map_depth:i32 = new_chunk_size * new_num_chunks_z;
+		if new_map_width == self.map_width as i32 && new_map_depth == self.map_depth as i32 && new_chunk_size == self.chunk_size as i32 && offset_x == 0 && offset_z == 0 {
+			return;
 		}
-		else {
-			for z in 0..new_map_size {
-				for x in 0..new_map_size {
-					let idx = (z as i32 - offset_z) * self.map_size as i32 + (x as i32 - offset_x);
-					let current_height:i32 = self.heights[idx as i32 as usize];
-					let new_idx = z * new_map_size + x;
-					new_heights[new_idx as usize] = current_height;
-				}
+
+		// Each axis grows or shrinks on its own, so cells are copied wherever the old and new maps overlap
+		let mut new_heights:Vec<i32> = vec![0; (new_map_width * new_map_depth) as usize];
+		for z in 0..new_map_depth {
+			let old_z = z - offset_z;
+			if old_z < 0 || old_z >= self.map_depth as i32 { continue; }
+			for x in 0..new_map_width {
+				let old_x = x - offset_x;
+				if old_x < 0 || old_x >= self.map_width as i32 { continue; }
+				new_heights[(z * new_map_width + x) as usize] = self.heights[old_z as usize * self.map_width + old_x as usize];
 			}
 		}
 
 		self.chunk_size = new_chunk_size as i64;
-		self.num_chunks = new_num_chunks as i64;
-		self.map_size = new_map_size as usize;
+		self.num_chunks_x = new_num_chunks_x as i64;
+		self.num_chunks_z = new_num_chunks_z as i64;
+		self.map_width = new_map_width as usize;
+		self.map_depth = new_map_depth as usize;
 		self.heights = new_heights;
 
 		self.edited_positions.clear();
@@ -572,7 +692,7 @@ impl Terrain {
 
 
 	fn set_height_at (&mut self, global_x:i32, global_z:i32, height:i32) {
-		let idx:i32 = global_z * self.map_size as i32 + global_x;
+		let idx:i32 = global_z * self.map_width as i32 + global_x;
 		self.heights[idx as usize] = height;
 	}
 
@@ -588,12 +708,18 @@ impl Terrain {
 
 		self.chunk_vertices[chunk_id].clear();
 		self.chunk_indices[chunk_id].clear();
-		let vertices = generate_chunk_mesh_from_height_array(
+		let mut vertices = generate_chunk_mesh_from_height_array(
 			&self.heights,
 			chunk_id, 
 			self.chunk_size as usize, 
-			self.num_chunks as usize
+			self.num_chunks_x as usize,
+			self.num_chunks_z as usize
 		);
+		if self.border_walls || self.border_cap {
+			let faces = border_faces(chunk_id, self.num_chunks_x as usize, self.num_chunks_z as usize);
+			let base_height = (self.base_height as i32).min(self.min_height) as f32;
+			add_border_walls(&mut vertices, faces, self.chunk_size as usize, base_height, self.border_walls, self.border_cap);
+		}
 
 		let mut vertex_map:HashMap<Vector3Key,usize> = HashMap::new();
 
@@ -618,20 +744,36 @@ impl Terrain {
 			}
 		}
 
+		let scaled:Vec<Vector3> = self.chunk_vertices[chunk_id].iter().map(|vert| Vector3::new(vert.x, vert.y * self.step_height, vert.z)).collect();
+		let (vertices, normals, indices) = crease_normals(&scaled, &self.chunk_indices[chunk_id], self.crease_angle);
+		let (uvs, uv_classes) = if self.generate_uvs {
+			let offset_x = self.chunk_size * (chunk_id as i64 % self.num_chunks_x);
+			let offset_z = self.chunk_size * (chunk_id as i64 / self.num_chunks_x);
+			generate_uvs(&vertices, &normals, Vector3::new(offset_x as f32, 0.0, offset_z as f32), self.uv_scale)
+		} else {
+			(Vec::new(), Vec::new())
+		};
+
         let st = SurfaceTool::new();
         st.begin(4);
 
-		let indices = self.chunk_indices[chunk_id].clone();
-
+		for i in 0..vertices.len() {
+			st.add_normal(normals[i]);
+			if self.generate_uvs {
+				st.add_uv(uvs[i]);
+				st.add_uv2(uv_classes[i]);
+			}
+			st.add_vertex(vertices[i]);
+		}
 		for index in indices.iter() {
-			let mut vert = self.chunk_vertices[chunk_id][*index];
-			vert.y *= self.step_height;
-			st.add_vertex(vert);
+			st.add_index(*index as i64);
 		}
-		st.index();
-		st.generate_normals(false);	
 
-		let mesh_flags = 1 + 2 + 4 + 16 + 256 + 512 + 1024  + 2048 + 8192 + 131072;
+		let mut mesh_flags = 1 + 2 + 4 + 16 + 256 + 512 + 1024  + 2048 + 8192 + 131072;
+		// Half float UVs can't address world space coordinates on large maps
+		if self.generate_uvs {
+			mesh_flags &= !(8192 | 16384);
+		}
         let mesh = st.commit(GodotObject::null(), mesh_flags);
         let mesh_instance = unsafe { self.chunks[chunk_id].assume_safe() };
         if let Some(mesh) = mesh {
@@ -663,9 +805,9 @@ impl Terrain {
 				let current_height:i32 = self.heights[*index];
 				new_redo.insert(*index, current_height);
 
-				let x = index % self.map_size;
-				let z = index / self.map_size;
-				let chunk_id:usize = (z / self.chunk_size as usize) * self.num_chunks as usize + (x / self.chunk_size as usize);
+				let x = index % self.map_width;
+				let z = index / self.map_width;
+				let chunk_id:usize = (z / self.chunk_size as usize) * self.num_chunks_x as usize + (x / self.chunk_size as usize);
 				self.heights[*index] = *height;
 				self.check_seam_condition(x as i32, z as i32);  
 				chunks_to_update.insert(chunk_id);
@@ -695,9 +837,9 @@ impl Terrain {
 				let current_height:i32 = self.heights[*index];
 				new_undo.insert(*index, current_height);
 
-				let x = index % self.map_size;
-				let z = index / self.map_size;
-				let chunk_id:usize= (z / self.chunk_size as usize) * self.num_chunks as usize + (x / self.chunk_size as usize);
+				let x = index % self.map_width;
+				let z = index / self.map_width;
+				let chunk_id:usize= (z / self.chunk_size as usize) * self.num_chunks_x as usize + (x / self.chunk_size as usize);
 				self.heights[*index] = *height;
 				self.check_seam_condition(x as i32, z as i32);  
 				chunks_to_update.insert(chunk_id);
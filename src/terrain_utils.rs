@@ -1,9 +1,22 @@
 use super::structs::*;
 use crate::marching_cubes::*;
+use crate::simplify::*;
+use crate::normals::*;
+use crate::uv::*;
+use crate::spline::*;
+use crate::selection::*;
+use crate::auto_paint::*;
+use crate::splat::*;
+use crate::chunk_mesher::*;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::borrow::Cow;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use rayon::prelude::*;
 use gdnative::api::ArrayMesh;
 use gdnative::api::File;
+use gdnative::api::Image;
 use gdnative::api::MeshDataTool;
 use gdnative::prelude::*;
 use gdnative::api::Resource;
@@ -14,14 +27,26 @@ use gdnative::api::MeshInstance;
 enum Action {
 	TerrainEdit(HashMap<usize,i32>),
 	VertexColor(HashMap<usize,Color8>),
+	SplatWeights(HashMap<usize,SplatWeights>),
+	// Material count and the weights of every cell
+	SplatChannels(usize, Vec<SplatWeights>),
+	// Whole map turns and flips, which are undone by turning or flipping back
+	Rotate(i32),
+	Flip(bool),
+	Batch(Vec<Action>),
 }
 
 #[derive(NativeClass)]
 #[inherit(Resource)]
+#[register_with(Self::register_signals)]
 pub struct TerrainUtils {
 	heights:Vec<i32>,
-	map_size:usize,
-	num_chunks:usize,
+	min_height:i32,
+	max_height:i32,
+	map_width:usize,
+	map_depth:usize,
+	num_chunks_x:usize,
+	num_chunks_z:usize,
 	chunk_size:usize,
 
 	undo_stack:Vec<Action>,
@@ -30,6 +55,9 @@ pub struct TerrainUtils {
 	vertex_colors:Vec<usize>,
 	color_list:Vec<Color8>,
 
+	splat_channels:usize, // Material weights per cell. Zero paints with vertex colors instead
+	splat_weights:Vec<SplatWeights>,
+
 	chunk_vertices:Vec<Vec<Vector3>>,
 	chunk_indices:Vec<Vec<usize>>,
 
@@ -38,70 +66,167 @@ pub struct TerrainUtils {
 	terrain_vertex_map: HashMap<Vector3Key,usize>,
 
 	shade_smooth:bool,
+	crease_angle:f32,
+	generate_uvs:bool,
+	uv_scale:f32,
+	simplify_flat:bool,
+	export_max_error:f32, // Decimates exported meshes when above zero
+	border_walls:bool,
+	border_cap:bool,
+	base_height:i32,
 
 	edited_positions:HashMap<usize,i32>,
 	edited_colors:HashMap<usize,Color8>,
+	edited_splats:HashMap<usize,SplatWeights>,
 
 	chunks_at_seam:HashSet<usize>,
 	chunks:Vec<Ref<MeshInstance>>,
 	is_drawing:bool,
 
 	chunk_lod:Vec<usize>,
-
-	active_chunk_position:(i32,i32),
+	lod_distances:Vec<f32>, // Distance in chunks at which each LOD after the first begins
+	max_lod:usize,
+	lod_hysteresis:f32,
+	lod_position:Option<(i32,i32)>,
+
+	splines:Vec<Spline>,
+
+	selection:Option<SelectionMask>,
+	clipboard:Option<TerrainRegion>,
+
+	symmetry_mode:i32,
+	symmetry_folds:i32,
+	symmetry_pivot:Option<Vector2>,
+
+	clone_source:Option<Vector2>,
+	clone_offset:Option<Vector2>,
+	clone_source_terrain:Option<TerrainRegion>,
+
+	paint_rules:Vec<PaintRule>,
+	auto_paint_on_stroke:bool,
+
+	async_remesh:bool,
+	chunk_versions:Vec<u64>, // Changes whenever a chunk's data does
+	shown_versions:Vec<u64>, // What each chunk's current mesh was built from
+	next_version:u64,
+	queued_chunks:HashSet<usize>,
+	building_chunks:HashMap<usize,u64>,
+	remesh_sender:Sender<ChunkResult>,
+	remesh_results:Receiver<ChunkResult>,
+	rebuild_requested:bool,
 }
 
 #[methods]
 impl TerrainUtils {
 
     fn new(_owner: &Resource) -> Self {
+		let (remesh_sender, remesh_results) = channel();
         TerrainUtils {
 
 			heights:Vec::new(),
-			map_size: 0,
-			num_chunks: 0,
+			min_height: DEFAULT_MIN_HEIGHT,
+			max_height: DEFAULT_MAX_HEIGHT,
+			map_width: 0,
+			map_depth: 0,
+			num_chunks_x: 0,
+			num_chunks_z: 0,
 			chunk_size: 0,
 
 			vertex_colors:Vec::new(),
 			color_list:Vec::new(),
 
+			splat_channels:0,
+			splat_weights:Vec::new(),
+
 			undo_stack:Vec::new(),
 			redo_stack:Vec::new(),
 
 			chunk_vertices:Vec::new(),
 			chunk_indices:Vec::new(),
 			chunk_lod:Vec::new(),
-			active_chunk_position:(0,0),
+			lod_distances:vec![2.0, 4.0, 8.0],
+			max_lod:3,
+			lod_hysteresis:0.25,
+			lod_position:None,
 
 			terrain_vertices:Vec::new(),
 			terrain_indices:Vec::new(),
 			terrain_vertex_map:HashMap::new(),
 
 			shade_smooth:true,
+			crease_angle:DEFAULT_CREASE_ANGLE,
+			generate_uvs:false,
+			uv_scale:1.0,
+			simplify_flat:false,
+			export_max_error:0.0,
+			border_walls:false,
+			border_cap:false,
+			base_height:DEFAULT_MIN_HEIGHT,
 
 			edited_positions: HashMap::new(),
 			edited_colors: HashMap::new(),
+			edited_splats: HashMap::new(),
 			chunks_at_seam: HashSet::new(),
 			chunks: Vec::new(),
 			is_drawing: false,
-			
+
+			splines: Vec::new(),
+
+			selection: None,
+			clipboard: None,
+
+			symmetry_mode: 0,
+			symmetry_folds: 2,
+			symmetry_pivot: None,
+
+			clone_source: None,
+			clone_offset: None,
+			clone_source_terrain: None,
+
+			paint_rules: Vec::new(),
+			auto_paint_on_stroke: false,
+
+			async_remesh: false,
+			chunk_versions: Vec::new(),
+			shown_versions: Vec::new(),
+			next_version: 1,
+			queued_chunks: HashSet::new(),
+			building_chunks: HashMap::new(),
+			remesh_sender: remesh_sender,
+			remesh_results: remesh_results,
+			rebuild_requested: false,
         }
     }
 
+	fn register_signals (builder:&ClassBuilder<Self>) {
+		// Sent by poll_rebuild for each chunk a background rebuild has put in place
+		builder.add_signal(Signal {
+			name: "chunk_mesh_ready",
+			args: &[SignalArgument {
+				name: "chunk_id",
+				default: Variant::from_i64(0),
+				export_info: ExportInfo::new(VariantType::I64),
+				usage: PropertyUsage::DEFAULT,
+			}],
+		});
+	}
+
 	#[export]
-	fn init_params (&mut self, _owner:&Resource, _num_chunks:i64, _chunk_size:i64, _heights:TypedArray<i32>, _color_list:TypedArray<Color>, _vertex_colors:TypedArray<i32>) {
-		self.num_chunks = _num_chunks.max(1) as usize;
+	fn init_params (&mut self, _owner:&Resource, _num_chunks_x:i64, _num_chunks_z:i64, _chunk_size:i64, _heights:TypedArray<i32>, _color_list:TypedArray<Color>, _vertex_colors:TypedArray<i32>) {
+		self.num_chunks_x = _num_chunks_x.max(1) as usize;
+		self.num_chunks_z = _num_chunks_z.max(1) as usize;
 		self.chunk_size = _chunk_size.max(1) as usize;
-		self.map_size = self.num_chunks * self.chunk_size;
+		self.map_width = self.num_chunks_x * self.chunk_size;
+		self.map_depth = self.num_chunks_z * self.chunk_size;
 		self.color_list.clear();
 		self.vertex_colors.clear();
 		self.chunk_vertices.clear();
 		self.chunk_indices.clear();
-		for _i in 0..self.num_chunks * self.num_chunks {
+		for _i in 0..self.chunk_count() {
 			self.chunk_vertices.push(Vec::new());
 			self.chunk_indices.push(Vec::new());
 		}
-		let total_map_size = self.map_size * self.map_size;
+		let total_map_size = self.map_width * self.map_depth;
 		self.heights = vec![0; total_map_size];
 		self.vertex_colors = vec![0; total_map_size];
 		self.color_list = vec![Color8::new(0,0,0,0); 1];
@@ -116,7 +241,12 @@ impl TerrainUtils {
 				self.color_list.push(Color8::from_color(_color_list.get(i)));
 			}
 		}
-		self.chunk_lod = vec![0; self.num_chunks * self.num_chunks];
+		self.splat_channels = 0;
+		self.splat_weights.clear();
+		self.reset_chunk_versions();
+		self.chunk_lod = vec![0; self.chunk_count()];
+		self.lod_position = None;
+		self.selection = None;
 	}
 	
 
@@ -143,39 +273,98 @@ impl TerrainUtils {
 		return color_list_length;
 	}
 
+	#[export]
+	fn set_lod_policy (&mut self, _owner:&Resource, distances:TypedArray<f32>, max_lod:i64, hysteresis:f32) {
+		self.lod_distances.clear();
+		for i in 0..distances.len() {
+			self.lod_distances.push(distances.get(i).max(0.0));
+		}
+		self.lod_distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+		self.max_lod = max_lod.max(0) as usize;
+		self.lod_hysteresis = hysteresis.max(0.0);
+		self.lod_position = None;
+	}
+
+	#[export]
+	fn get_lod_distances (&self, _owner:&Resource) -> TypedArray<f32> {
+		return TypedArray::from_vec(self.lod_distances.clone());
+	}
+	#[export]
+	fn get_max_lod (&self, _owner:&Resource) -> i64 { self.max_lod as i64 }
+	#[export]
+	fn get_lod_hysteresis (&self, _owner:&Resource) -> f32 { self.lod_hysteresis }
+
+	// A chunk only moves to another LOD once it is `lod_hysteresis` chunks past the threshold, so it doesn't flicker at the boundary
+	fn lod_for_distance (&self, distance:f32, current_lod:usize) -> usize {
+		let max_lod = self.max_lod.min(self.lod_distances.len());
+		let mut lod = current_lod.min(max_lod);
+		while lod < max_lod && distance >= self.lod_distances[lod] + self.lod_hysteresis {
+			lod += 1;
+		}
+		while lod > 0 && distance < self.lod_distances[lod - 1] - self.lod_hysteresis {
+			lod -= 1;
+		}
+		return lod;
+	}
+
 	#[export]
 	fn update_lod (&mut self, _owner:&Resource, global_x:i32, global_z:i32) {
-		let chunk_col = global_x / self.chunk_size as i32;
-		let chunk_row = global_z / self.chunk_size as i32;
-		
-		if self.active_chunk_position.0 != chunk_col || self.active_chunk_position.1 != chunk_row {
-			self.active_chunk_position = (chunk_col, chunk_row);
-			let mut chunks_to_update:Vec<usize> = Vec::new();
-			for i in 0..self.chunk_lod.len() {
-				let dist = Vector2::new(chunk_col as f32, chunk_row as f32).distance_to(Vector2::new((i % self.num_chunks) as f32, (i / self.num_chunks) as f32));
-				let lod = (dist / 1.0).floor() as usize;
-				self.chunk_lod[i] = lod;
-				chunks_to_update.push(i);
-			}
-			if chunks_to_update.len() > 0 {
-				for i in chunks_to_update.iter() {
-					self.update_chunk(_owner, *i);
-					self.generate_chunk_mesh(_owner, *i);
-				}
-			}
+		if self.lod_position == Some((global_x, global_z)) {
+			return;
+		}
+		self.lod_position = Some((global_x, global_z));
+
+		let position = Vector2::new(global_x as f32, global_z as f32) / self.chunk_size as f32;
+		let mut new_lod:Vec<usize> = Vec::with_capacity(self.chunk_lod.len());
+		for i in 0..self.chunk_lod.len() {
+			let center = Vector2::new((i % self.num_chunks_x) as f32 + 0.5, (i / self.num_chunks_x) as f32 + 0.5);
+			new_lod.push(clamp_lod(self.lod_for_distance(position.distance_to(center), self.chunk_lod[i]), self.chunk_size));
 		}
+		self.apply_lod(new_lod);
 	}
 
 	#[export]
 	fn set_chunk_lod (&mut self, _owner:&Resource, chunk_id:usize, lod:usize) {
-		self.chunk_lod[chunk_id] = lod;
-		self.update_chunk(_owner, chunk_id);
-		self.generate_chunk_mesh(_owner, chunk_id);
+		if chunk_id >= self.chunk_lod.len() {
+			return;
+		}
+		let mut new_lod = self.chunk_lod.clone();
+		new_lod[chunk_id] = clamp_lod(lod, self.chunk_size);
+		self.apply_lod(new_lod);
+	}
+
+	// Takes new LODs for every chunk, lowered where needed so transition cells only ever stitch one level
+	fn apply_lod (&mut self, mut new_lod:Vec<usize>) {
+		limit_lod_steps(&mut new_lod, self.num_chunks_x, self.num_chunks_z);
+
+		// Neighbours of a changed chunk are rebuilt too so their transition cells match
+		let mut chunks_to_update:HashSet<usize> = HashSet::new();
+		for i in 0..new_lod.len() {
+			if new_lod[i] != self.chunk_lod[i] {
+				let col = i % self.num_chunks_x;
+				let row = i / self.num_chunks_x;
+				chunks_to_update.insert(i);
+				if col > 0 {
+					chunks_to_update.insert(i - 1);
+				}
+				if col < self.num_chunks_x - 1 {
+					chunks_to_update.insert(i + 1);
+				}
+				if row > 0 {
+					chunks_to_update.insert(i - self.num_chunks_x);
+				}
+				if row < self.num_chunks_z - 1 {
+					chunks_to_update.insert(i + self.num_chunks_x);
+				}
+			}
+		}
+		self.chunk_lod = new_lod;
+		self.rebuild_chunks(&chunks_to_update.into_iter().collect::<Vec<usize>>());
 	}
 
 	fn set_vertex_color (&mut self, x:i32, z:i32, color:Color8) {
-		let x = x.max(0).min(self.map_size as i32 - 1) as usize;
-		let z = z.max(0).min(self.map_size as i32 - 1) as usize;
+		let x = x.max(0).min(self.map_width as i32 - 1) as usize;
+		let z = z.max(0).min(self.map_depth as i32 - 1) as usize;
 		// Check if color exists and grab its color_index
 		let color_list_length = self.color_list.len();
 		let mut new_color_index = color_list_length;
@@ -189,20 +378,37 @@ impl TerrainUtils {
 		if new_color_index == color_list_length {
 			self.color_list.push(color);
 		}
-		self.vertex_colors[z * self.map_size + x] = new_color_index;
+		self.vertex_colors[z * self.map_width + x] = new_color_index;
 	}
 
 	#[export]
-	fn get_num_chunks (&self, _owner:&Resource) -> i64 { self.num_chunks as i64 }
+	fn get_num_chunks_x (&self, _owner:&Resource) -> i64 { self.num_chunks_x as i64 }
+	#[export]
+	fn get_num_chunks_z (&self, _owner:&Resource) -> i64 { self.num_chunks_z as i64 }
+	#[export]
+	fn get_chunk_count (&self, _owner:&Resource) -> i64 { self.chunk_count() as i64 }
+	#[export]
+	fn get_map_width (&self, _owner:&Resource) -> i64 { self.map_width as i64 }
+	#[export]
+	fn get_map_depth (&self, _owner:&Resource) -> i64 { self.map_depth as i64 }
 	#[export]
 	fn get_chunk_size (&self, _owner:&Resource) -> i64 { self.chunk_size as i64 }
 	
 
 	fn get_vertex_color (&self, x:i32, z:i32) -> Color8 {
-		let x = x.max(0).min(self.map_size as i32 - 1) as usize;
-		let z = z.max(0).min(self.map_size as i32 - 1) as usize;
-		let color_index = self.vertex_colors[z * self.map_size + x];
-		return self.color_list[color_index];
+		return self.color_list[self.vertex_color_index(x, z)];
+	}
+
+	fn vertex_color_index (&self, x:i32, z:i32) -> usize {
+		let x = x.max(0).min(self.map_width as i32 - 1) as usize;
+		let z = z.max(0).min(self.map_depth as i32 - 1) as usize;
+		return self.vertex_colors[z * self.map_width + x];
+	}
+
+	fn splat_weights_at (&self, x:i32, z:i32) -> SplatWeights {
+		let x = x.max(0).min(self.map_width as i32 - 1) as usize;
+		let z = z.max(0).min(self.map_depth as i32 - 1) as usize;
+		return self.splat_weights[z * self.map_width + x];
 	}
 
 	
@@ -213,31 +419,47 @@ impl TerrainUtils {
 		//for i in 0..self.heights.len() {
 		//	self.heights[i] = 0;
 		//}
-		self.heights = vec![0; self.map_size * self.map_size];
+		self.heights = vec![0; self.map_width * self.map_depth];
 	}
 
 	fn height (&self, global_x:i32, global_z:i32) -> i32 {
-		let idx:i32 = global_z * self.map_size as i32 + global_x;
+		let idx:i32 = global_z * self.map_width as i32 + global_x;
 		return self.heights[idx as usize];
 	}
 
 	fn height_by_index (&self, index:usize) -> i32 {
-		let x = index % self.map_size;
-		let z = index / self.map_size;
+		let x = index % self.map_width;
+		let z = index / self.map_width;
 		return self.height(x as i32, z as i32);
 	}
 
 	fn set_height_at (&mut self, global_x:i32, global_z:i32, height:i32) {
-		let idx:i32 = global_z * self.map_size as i32 + global_x;
+		let idx:i32 = global_z * self.map_width as i32 + global_x;
 		self.heights[idx as usize] = height;
 	}
 
 	fn set_height_by_index (&mut self, index:usize, height:i32) {
-		let x = index % self.map_size;
-		let z = index / self.map_size;
+		let x = index % self.map_width;
+		let z = index / self.map_width;
 		self.set_height_at(x as i32,z as i32, height);
 	}
 
+	fn clamp_height (&self, height:i32) -> i32 {
+		return height.clamp(self.min_height, self.max_height);
+	}
+
+	// Limits edits to [min_height, max_height]. Existing heights outside the range are left alone
+	#[export]
+	pub fn set_height_range (&mut self, _owner:&Resource, min_height:i32, max_height:i32) {
+		self.min_height = min_height.min(max_height);
+		self.max_height = min_height.max(max_height);
+	}
+
+	#[export]
+	pub fn get_min_height (&self, _owner:&Resource) -> i32 { self.min_height }
+	#[export]
+	pub fn get_max_height (&self, _owner:&Resource) -> i32 { self.max_height }
+
 	#[export]
 	pub fn get_heights(&self, _owner:&Resource) -> TypedArray<i32>{
 		let mut arr:TypedArray<i32> = TypedArray::new();
@@ -268,11 +490,11 @@ impl TerrainUtils {
 	#[export]
 	fn get_heights_for_collision (&mut self, _owner:&Resource) -> TypedArray<f32> {
 		let mut v:Vec<f32> = Vec::new();
-		let map_sized_plus = self.map_size + 1;
-		for z in 0..map_sized_plus {
-			let z_min = z.min(self.map_size - 1);
-			for x in 0..map_sized_plus {
-				let x_min = x.min(self.map_size - 1);
+		// Rows of map_width + 1 samples, map_depth + 1 rows deep
+		for z in 0..(self.map_depth + 1) {
+			let z_min = z.min(self.map_depth - 1);
+			for x in 0..(self.map_width + 1) {
+				let x_min = x.min(self.map_width - 1);
 				let y = self.height(x_min as i32, z_min as i32);
 				v.push(y as f32 + 0.5);
 			}
@@ -283,17 +505,17 @@ impl TerrainUtils {
 	
 	#[export]
 	fn get_height (&self, _owner:&Resource, global_x:i32, global_z:i32) -> i32 {
-		let x_clamp = (global_x.max(0) as i32).min(self.map_size as i32 - 1);
-		let z_clamp = (global_z.max(0) as i32).min(self.map_size as i32 - 1);
-		//let idx:i32 =  z_clamp * self.map_size as i32 + x_clamp;
+		let x_clamp = (global_x.max(0) as i32).min(self.map_width as i32 - 1);
+		let z_clamp = (global_z.max(0) as i32).min(self.map_depth as i32 - 1);
+		//let idx:i32 =  z_clamp * self.map_width as i32 + x_clamp;
 		return self.height(x_clamp, z_clamp);
 	}
 
 	#[export]
 	fn get_color (&self, _owner:&Resource, global_x:i32, global_z:i32) -> Color {
-		let x_clamp = (global_x.max(0) as i32).min(self.map_size as i32 - 1);
-		let z_clamp = (global_z.max(0) as i32).min(self.map_size as i32 - 1);
-		let idx:i32 =  z_clamp * self.map_size as i32 + x_clamp;
+		let x_clamp = (global_x.max(0) as i32).min(self.map_width as i32 - 1);
+		let z_clamp = (global_z.max(0) as i32).min(self.map_depth as i32 - 1);
+		let idx:i32 =  z_clamp * self.map_width as i32 + x_clamp;
 		return self.color_list[self.vertex_colors[idx as usize]].to_color();
 	}
 
@@ -302,9 +524,60 @@ impl TerrainUtils {
 		self.shade_smooth = value;
 	}
 
+	// Faces meeting at more than this many degrees are shaded with a hard edge when shading smooth
+	#[export]
+	pub fn set_crease_angle (&mut self, _owner:&Resource, degrees:f32) {
+		self.crease_angle = degrees.clamp(0.0, 180.0);
+	}
+
+	#[export]
+	pub fn get_crease_angle (&self, _owner:&Resource) -> f32 { self.crease_angle }
+
+	fn shading_crease_angle (&self) -> f32 {
+		return if self.shade_smooth { self.crease_angle } else { 0.0 };
+	}
+
+	// UV holds world space texture coordinates and UV2 the top or wall classification. Takes effect when meshes are next generated
+	#[export]
+	pub fn set_uv_generation (&mut self, _owner:&Resource, enabled:bool, scale:f32) {
+		self.generate_uvs = enabled;
+		self.uv_scale = scale;
+	}
+
+	// Half float UVs can't address world space coordinates on large maps, so UV compression is left off while generating them
+	fn mesh_compress_flags (&self) -> i64 {
+		let compress_tex_uvs = 8192 | 16384;
+		return if self.generate_uvs { 2194432 & !compress_tex_uvs } else { 2194432 };
+	}
+
+	// Takes effect the next time chunks are updated
+	#[export]
+	pub fn set_simplify_flat (&mut self, _owner:&Resource, value:bool) {
+		self.simplify_flat = value;
+	}
+
+	#[export]
+	pub fn set_export_max_error (&mut self, _owner:&Resource, max_error:f32) {
+		self.export_max_error = max_error.max(0.0);
+	}
+
+	// Walls down to base_height around the map and a floor under it. The base never goes above the lowest allowed height
+	#[export]
+	pub fn set_border (&mut self, _owner:&Resource, walls:bool, cap:bool, base_height:i32) {
+		self.border_walls = walls;
+		self.border_cap = cap;
+		self.base_height = base_height;
+		self.update_all_chunks(_owner);
+		self.generate_all_meshes(_owner);
+		self.update_terrain_arrays();
+	}
+
+	#[export]
+	pub fn get_base_height (&self, _owner:&Resource) -> i32 { self.base_height.min(self.min_height) }
+
 	#[export]
 	pub fn in_bounds (&mut self, _owner:&Resource, global_x:i32, global_z:i32) -> bool {
-		return global_x > -1 && global_z > -1 && global_x < self.map_size as i32 && global_z < self.map_size as i32;
+		return global_x > -1 && global_z > -1 && global_x < self.map_width as i32 && global_z < self.map_depth as i32;
 	}
 
 	#[export]
@@ -319,31 +592,61 @@ impl TerrainUtils {
 	#[export]
 	pub fn end_stroke(&mut self, _owner:&Resource) {
 		self.is_drawing = false;
-		if self.edited_positions.len() > 0 {
+		// Repaint around the sculpted cells. The previous colors join the stroke's undo step
+		if self.auto_paint_on_stroke && self.paint_rules.len() > 0 && self.edited_positions.len() > 0 {
+			let cells = self.cells_around(&self.edited_positions.keys().cloned().collect::<Vec<usize>>(), 1);
+			let new_colors = self.auto_paint_colors(&cells, true);
+			let mut dirty_chunks:HashSet<usize> = HashSet::new();
+			for (index, color) in self.write_colors(&new_colors, &mut dirty_chunks) {
+				self.edited_colors.entry(index).or_insert(color);
+			}
+			self.chunks_at_seam.extend(dirty_chunks);
+		}
+		// Clone strokes can touch heights and colors, which are undone together
+		if self.edited_positions.len() > 0 && self.edited_colors.len() > 0 {
+			self.undo_stack.push(Action::Batch(vec![
+				Action::TerrainEdit(self.edited_positions.clone()),
+				Action::VertexColor(self.edited_colors.clone())
+			]));
+		}
+		else if self.edited_positions.len() > 0 {
 			self.undo_stack.push(Action::TerrainEdit(self.edited_positions.clone()));
 		}
-		for id in self.chunks_at_seam.iter().cloned().collect::<Vec<usize>>() {
-			self.update_chunk(_owner, id);
-			self.generate_chunk_mesh(_owner, id);
+		else if self.edited_colors.len() > 0 {
+			self.undo_stack.push(Action::VertexColor(self.edited_colors.clone()));
 		}
+		let seam_chunks = self.chunks_at_seam.iter().cloned().collect::<Vec<usize>>();
+		self.rebuild_chunks(&seam_chunks);
 		self.edited_positions.clear();
+		self.edited_colors.clear();
 		self.chunks_at_seam.clear();
 	}
 
 	#[export]
 	pub fn end_paint_stroke(&mut self, _owner:&Resource) {
 		self.is_drawing = false;
-		if self.edited_colors.len() > 0 {
+		// A stroke that paints colors and splat weights is still one undo step
+		if self.edited_colors.len() > 0 && self.edited_splats.len() > 0 {
+			self.undo_stack.push(Action::Batch(vec![
+				Action::VertexColor(self.edited_colors.clone()),
+				Action::SplatWeights(self.edited_splats.clone())
+			]));
+		}
+		else if self.edited_colors.len() > 0 {
 			self.undo_stack.push(Action::VertexColor(self.edited_colors.clone()));
 		}
+		else if self.edited_splats.len() > 0 {
+			self.undo_stack.push(Action::SplatWeights(self.edited_splats.clone()));
+		}
 		self.edited_colors.clear();
+		self.edited_splats.clear();
 	}
 
 	fn get_grid_positions_in_radius (&self, global_x:i32, global_z:i32, radius:i32) -> Vec<(i32,i32,i32)>{
 		let mut positions:Vec<(i32,i32,i32)> = Vec::new();
 
 		let mut add_position = |gx:i32, gz:i32| {
-			if gx > -1 && gz > -1 && gx < self.map_size as i32 && gz < self.map_size as i32 {
+			if gx > -1 && gz > -1 && gx < self.map_width as i32 && gz < self.map_depth as i32 {
 				positions.push((gx as i32, self.height(gx as i32, gz as i32), gz as i32));
 			}
 		};
@@ -387,7 +690,7 @@ impl TerrainUtils {
 
 	#[export]
 	fn clear_vertex_colors (&mut self, _owner:&Resource) {
-		self.vertex_colors = vec![0; self.map_size * self.map_size];
+		self.vertex_colors = vec![0; self.map_width * self.map_depth];
 		self.color_list = vec![Color8::new(0,0,0,0); 1]
 	}
 
@@ -405,13 +708,13 @@ impl TerrainUtils {
 
 		for z in -radius..(radius + 1) {
 			let global_z = position.z as i32 + z;
-			if global_z < 0 || global_z >= self.map_size as i32 { continue; }
+			if global_z < 0 || global_z >= self.map_depth as i32 { continue; }
 			let chunk_row = global_z as usize / self.chunk_size;
 			for x in -radius..(radius + 1) {
 				let global_x = position.x as i32 + x;
-				if global_x < 0 || global_x >= self.map_size as i32 { continue; }
+				if global_x < 0 || global_x >= self.map_width as i32 { continue; }
 				let chunk_col = global_x as usize / self.chunk_size;
-				let chunk_id = chunk_row * self.num_chunks + chunk_col;
+				let chunk_id = chunk_row * self.num_chunks_x + chunk_col;
 				chunks_to_update.insert(chunk_id);
 			}
 		}
@@ -429,8 +732,8 @@ impl TerrainUtils {
 				mdt.create_from_surface(mesh.clone(), 0).unwrap();
 
 
-				let offset_x = self.chunk_size as i32 * (*chunk_id as i32 % self.num_chunks as i32);
-				let offset_z = self.chunk_size as i32 * (*chunk_id as i32 / self.num_chunks as i32);
+				let offset_x = self.chunk_size as i32 * (*chunk_id as i32 % self.num_chunks_x as i32);
+				let offset_z = self.chunk_size as i32 * (*chunk_id as i32 / self.num_chunks_x as i32);
 				
 				for i in 0..mdt.get_face_count() {
 
@@ -487,31 +790,171 @@ impl TerrainUtils {
 		}
 	}*/
 
+	// mode: 0 off, 1 mirror across the X axis, 2 mirror across the Z axis, 3 both, 4 rotational with `folds` copies.
+	// The pivot defaults to the map centre unless use_pivot is set
+	#[export]
+	pub fn set_symmetry (&mut self, _owner:&Resource, mode:i32, folds:i32, use_pivot:bool, pivot:Vector2) {
+		self.symmetry_mode = mode;
+		self.symmetry_folds = folds.max(2);
+		self.symmetry_pivot = if use_pivot { Some(pivot) } else { None };
+	}
+
+	// Rules are dictionaries, see PaintRule::from_dictionary. They run in order, so later rules paint over earlier ones
+	#[export]
+	pub fn set_paint_rules (&mut self, _owner:&Resource, rules:VariantArray) {
+		self.paint_rules.clear();
+		for item in rules.iter() {
+			self.paint_rules.push(PaintRule::from_dictionary(&item.to_dictionary()));
+		}
+	}
+
+	#[export]
+	pub fn get_paint_rules (&self, _owner:&Resource) -> VariantArray {
+		let arr = VariantArray::new();
+		for rule in self.paint_rules.iter() {
+			arr.push(rule.to_dictionary());
+		}
+		return arr.into_shared();
+	}
+
+	#[export]
+	pub fn set_auto_paint_on_stroke (&mut self, _owner:&Resource, value:bool) {
+		self.auto_paint_on_stroke = value;
+	}
+
+	#[export]
+	pub fn auto_paint (&mut self, _owner:&Resource, use_selection:bool) -> bool {
+		let cells = (0..self.heights.len()).collect::<Vec<usize>>();
+		let new_colors = self.auto_paint_colors(&cells, use_selection);
+		return self.apply_global_edits(_owner, &HashMap::new(), &new_colors);
+	}
+
+	fn cell_shape (&self, x:usize, z:usize) -> CellShape {
+		let x = x as i32;
+		let z = z as i32;
+		let last_x = self.map_width as i32 - 1;
+		let last_z = self.map_depth as i32 - 1;
+		return CellShape::from_neighbours(
+			self.height(x, z),
+			self.height((x - 1).max(0), z),
+			self.height((x + 1).min(last_x), z),
+			self.height(x, (z - 1).max(0)),
+			self.height(x, (z + 1).min(last_z))
+		);
+	}
+
+	// Colors after running the paint rules over the given cells. Only cells that change are returned
+	fn auto_paint_colors (&self, cells:&Vec<usize>, use_selection:bool) -> HashMap<usize,Color8> {
+		let mut new_colors:HashMap<usize,Color8> = HashMap::new();
+		for index in cells.iter() {
+			let weight = if use_selection { self.selection_weight(*index) } else { 1.0 };
+			if weight <= 0.0 { continue; }
+
+			let x = index % self.map_width;
+			let z = index / self.map_width;
+			let shape = self.cell_shape(x, z);
+			let current_color = self.color_list[self.vertex_colors[*index]];
+			let mut color = current_color;
+			for (i, rule) in self.paint_rules.iter().enumerate() {
+				if rule.matches(&shape, x, z, i as u32) {
+					color = color.blend(rule.color, rule.strength * weight, rule.blend_mode);
+				}
+			}
+			if color != current_color {
+				new_colors.insert(*index, color);
+			}
+		}
+		return new_colors;
+	}
+
+	// The given cells and every cell within `radius` of them
+	fn cells_around (&self, cells:&Vec<usize>, radius:i32) -> Vec<usize> {
+		let mut around:HashSet<usize> = HashSet::new();
+		for index in cells.iter() {
+			let x = (index % self.map_width) as i32;
+			let z = (index / self.map_width) as i32;
+			for dz in -radius..=radius {
+				for dx in -radius..=radius {
+					let (nx, nz) = (x + dx, z + dz);
+					if nx > -1 && nz > -1 && nx < self.map_width as i32 && nz < self.map_depth as i32 {
+						around.insert(nz as usize * self.map_width + nx as usize);
+					}
+				}
+			}
+		}
+		return around.into_iter().collect::<Vec<usize>>();
+	}
+
+	// The brush position, unchanged, followed by its mirrored copies. Copies that land on the same cell as an
+	// earlier position are dropped so a brush on the mirror line is not applied twice
+	fn symmetric_positions (&self, position:Vector2) -> Vec<Vector2> {
+		let center = Vector2::new(self.map_width as f32 - 1.0, self.map_depth as f32 - 1.0) * 0.5;
+		let pivot = self.symmetry_pivot.unwrap_or(center);
+		let mirror_x = Vector2::new(position.x, 2.0 * pivot.y - position.y);
+		let mirror_z = Vector2::new(2.0 * pivot.x - position.x, position.y);
+
+		let mut candidates:Vec<Vector2> = vec![position];
+		match self.symmetry_mode {
+			1 => candidates.push(mirror_x),
+			2 => candidates.push(mirror_z),
+			3 => {
+				candidates.push(mirror_x);
+				candidates.push(mirror_z);
+				candidates.push(pivot * 2.0 - position);
+			}
+			4 => {
+				let offset = position - pivot;
+				for fold in 1..self.symmetry_folds {
+					let angle = std::f32::consts::PI * 2.0 * fold as f32 / self.symmetry_folds as f32;
+					let (sin, cos) = angle.sin_cos();
+					candidates.push(pivot + Vector2::new(offset.x * cos - offset.y * sin, offset.x * sin + offset.y * cos));
+				}
+			}
+			_ => {}
+		}
+
+		let cell = |p:&Vector2| (p.x.round() as i32, p.y.round() as i32);
+		let mut positions:Vec<Vector2> = vec![position];
+		for candidate in candidates.iter().skip(1) {
+			if !positions.iter().any(|p| cell(p) == cell(candidate)) {
+				positions.push(*candidate);
+			}
+		}
+		return positions;
+	}
+
 	#[export]
 	pub fn paint_vertex (&mut self, _owner:&Resource, position:Vector3, brush_radius:f32, color:Color, opacity:f32, blend_mode:i32) {
 		if !self.is_drawing {
 			self.is_drawing = true;
 			self.edited_positions.clear();
+			self.edited_colors.clear();
+			self.edited_splats.clear();
 			self.redo_stack.clear();
 		}
 
 		let color8 = Color8::from_color(color);
+		let blend_mode = BlendMode::from_i32(blend_mode);
 
 		let mut chunks_to_update:HashSet<usize> = HashSet::new();
 
 		let radius = brush_radius as i32;
 		let radius_squared = brush_radius * brush_radius * 0.25;
 
-		for z in -radius..(radius + 1) {
-			let global_z = position.z as i32 + z;
-			if global_z < 0 || global_z >= self.map_size as i32 { continue; }
-			let chunk_row = global_z as usize / self.chunk_size;
-			for x in -radius..(radius + 1) {
-				let global_x = position.x as i32 + x;
-				if global_x < 0 || global_x >= self.map_size as i32 { continue; }
-				let chunk_col = global_x as usize / self.chunk_size;
-				let chunk_id = chunk_row * self.num_chunks + chunk_col;
-				chunks_to_update.insert(chunk_id);
+		let dabs:Vec<Vector2> = self.symmetric_positions(Vector2::new(position.x, position.z));
+
+		for dab in dabs.iter() {
+			for z in -radius..(radius + 1) {
+				let global_z = dab.y as i32 + z;
+				if global_z < 0 || global_z >= self.map_depth as i32 { continue; }
+				let chunk_row = global_z as usize / self.chunk_size;
+				for x in -radius..(radius + 1) {
+					let global_x = dab.x as i32 + x;
+					if global_x < 0 || global_x >= self.map_width as i32 { continue; }
+					let chunk_col = global_x as usize / self.chunk_size;
+					let chunk_id = chunk_row * self.num_chunks_x + chunk_col;
+					chunks_to_update.insert(chunk_id);
+				}
 			}
 		}
 
@@ -520,8 +963,8 @@ impl TerrainUtils {
 		/*for chunk_id in chunks_to_update.iter() {
 			for i in 0..self.chunk_vertices[*chunk_id].len() {
 				let vert = self.chunk_vertices[*chunk_id][i];
-				let offset_x = self.chunk_size as i32 * (*chunk_id as i32 % self.num_chunks as i32);
-				let offset_z = self.chunk_size as i32 * (*chunk_id as i32 / self.num_chunks as i32);
+				let offset_x = self.chunk_size as i32 * (*chunk_id as i32 % self.num_chunks_x as i32);
+				let offset_z = self.chunk_size as i32 * (*chunk_id as i32 / self.num_chunks_x as i32);
 				let mut global_vert = vert + Vector3::new(offset_x as f32, 0.0, offset_z as f32);
 
 				let diff:Vector3 = Vector3::new(global_vert.x - position.x, global_vert.y - position.y, global_vert.z - position.z);
@@ -560,24 +1003,25 @@ impl TerrainUtils {
 				let mesh = unsafe { mesh.assume_safe() };
 				mdt.create_from_surface(mesh.clone(), 0).unwrap();
 
-				let offset_x = self.chunk_size as i32 * (*chunk_id as i32 % self.num_chunks as i32);
-				let offset_z = self.chunk_size as i32 * (*chunk_id as i32 / self.num_chunks as i32);
+				let offset_x = self.chunk_size as i32 * (*chunk_id as i32 % self.num_chunks_x as i32);
+				let offset_z = self.chunk_size as i32 * (*chunk_id as i32 / self.num_chunks_x as i32);
 				
 				for i in 0..mdt.get_vertex_count() {
 					let local_vert = mdt.get_vertex(i);
 					let local_vert = Vector2::new(local_vert.x, local_vert.z);
 					let global_vert = local_vert + Vector2::new(offset_x as f32, offset_z as f32);
 		
-					let diff:Vector2 = Vector2::new(global_vert.x - position.x,  global_vert.y - position.z);
+					let in_brush = dabs.iter().any(|dab| (global_vert - *dab).square_length() <= radius_squared);
 		
-					if diff.square_length() <= radius_squared {
-						let x = (global_vert.x.floor() as i32).max(0).min(self.map_size as i32 - 1) as usize;
-						let z = (global_vert.y.floor() as i32).max(0).min(self.map_size as i32 - 1) as usize;
-						let index = z * self.map_size + x;
+					if in_brush {
+						let x = (global_vert.x.floor() as i32).max(0).min(self.map_width as i32 - 1) as usize;
+						let z = (global_vert.y.floor() as i32).max(0).min(self.map_depth as i32 - 1) as usize;
+						let index = z * self.map_width + x;
+						let weight = self.selection_weight(index);
 
-						if !self.edited_colors.contains_key(&index) {
+						if weight > 0.0 && !self.edited_colors.contains_key(&index) {
 							let current_color = self.color_list[self.vertex_colors[index]];
-							let new_color:Color8 = current_color.blend(color8, opacity, blend_mode); 
+							let new_color:Color8 = current_color.blend(color8, opacity * weight, blend_mode); 
 							self.set_vertex_color(x as i32, z as i32, new_color);
 							self.edited_colors.insert(index, current_color);
 							mdt.set_vertex_color(i, new_color.to_color());
@@ -591,55 +1035,306 @@ impl TerrainUtils {
 				mdt.commit_to_surface(mesh).unwrap();
 			}
 		}
+		for chunk_id in chunks_to_update.iter() {
+			self.repaint_chunk(*chunk_id);
+		}
 	}
 
-	/*pub fn color_by_slope (&mut self, _owner:&Resource, min_slope:f32, max_slope:f32, color:Color, strength:f32, blend_mode:i32) {
-		let color8 = Color8::from_color(color);
-		let mut changed_verts:HashSet<Vector2Key> = HashSet::new();
-		for chunk_id in 0..self.chunk_normals.len() {
-
-			let offset_x = self.chunk_size as i32 * (chunk_id as i32 % self.num_chunks as i32);
-			let offset_z = self.chunk_size as i32 * (chunk_id as i32 / self.num_chunks as i32);
-
-			for i in 0..self.chunk_normals[chunk_id].len() {
-				let normal = self.chunk_normals[chunk_id][i];
-				let slope = 1.0 - normal.dot(Vector3::new(0.0, 1.0, 0.0));
-				if slope >= min_slope && slope <= max_slope {
-					let local_vert = self.chunk_vertices[chunk_id][i];
-					let global_vert = local_vert + Vector3::new(offset_x as f32, 0.0, offset_z as f32);
-					let global_vert_key = Vector2Key::from_vector3(global_vert);
-					if !changed_verts.contains(&global_vert_key) {
-						let current_color = self.get_vertex_color(global_vert_key);
-						let new_color:Color8 = current_color.blend(color8, strength, blend_mode); 
-						self.set_vertex_color(global_vert_key, new_color);
-						changed_verts.insert(global_vert_key);
-					}
+	// Switches painting to material weights with `channels` materials, or back to vertex colors with zero.
+	// Cells keep their weights for the materials that remain. Undoing the change brings the old weights back
+	#[export]
+	pub fn set_splat_channels (&mut self, _owner:&Resource, channels:i64) {
+		let channels = (channels.max(0) as usize).min(MAX_SPLAT_CHANNELS);
+		if channels == self.splat_channels {
+			return;
+		}
+		let new_weights:Vec<SplatWeights> = if channels == 0 {
+			Vec::new()
+		}
+		else if self.splat_weights.len() != self.map_width * self.map_depth {
+			vec![full_weight(0); self.map_width * self.map_depth]
+		}
+		else {
+			self.splat_weights.iter().map(|weights| normalize(weights, channels)).collect()
+		};
+		let prev = self.write_splat_channels(_owner, channels, new_weights);
+		self.push_undo(prev);
+	}
+
+	// Replaces the material count and all weights, returning the previous ones as an undo action
+	fn write_splat_channels (&mut self, owner:&Resource, channels:usize, weights:Vec<SplatWeights>) -> Action {
+		let prev_weights = std::mem::replace(&mut self.splat_weights, weights);
+		let prev = Action::SplatChannels(self.splat_channels, prev_weights);
+		self.splat_channels = channels;
+		self.edited_splats.clear();
+		if self.simplify_flat {
+			self.update_all_chunks(owner);
+		}
+		self.generate_all_meshes(owner);
+		return prev;
+	}
+
+	#[export]
+	pub fn get_splat_channels (&self, _owner:&Resource) -> i64 { self.splat_channels as i64 }
+
+	// Raises `channel` by strength (0 to 1) in every cell under the brush, taking the weight from the other materials.
+	// A negative strength lowers it instead. Like paint_vertex, each cell is painted once per stroke
+	#[export]
+	pub fn paint_splat (&mut self, _owner:&Resource, position:Vector3, brush_radius:f32, channel:i64, strength:f32) {
+		if self.splat_channels == 0 || channel < 0 || channel as usize >= self.splat_channels {
+			return;
+		}
+		if !self.is_drawing {
+			self.is_drawing = true;
+			self.edited_positions.clear();
+			self.edited_colors.clear();
+			self.edited_splats.clear();
+			self.redo_stack.clear();
+		}
+
+		let radius = brush_radius as i32;
+		let radius_squared = brush_radius * brush_radius * 0.25;
+		let dabs:Vec<Vector2> = self.symmetric_positions(Vector2::new(position.x, position.z));
+
+		let mut new_splats:HashMap<usize,SplatWeights> = HashMap::new();
+		for dab in dabs.iter() {
+			for z in -radius..(radius + 1) {
+				let global_z = dab.y as i32 + z;
+				if global_z < 0 || global_z >= self.map_depth as i32 { continue; }
+				for x in -radius..(radius + 1) {
+					let global_x = dab.x as i32 + x;
+					if global_x < 0 || global_x >= self.map_width as i32 { continue; }
+					if (Vector2::new(global_x as f32, global_z as f32) - *dab).square_length() > radius_squared { continue; }
+
+					let index = global_z as usize * self.map_width + global_x as usize;
+					let weight = self.selection_weight(index);
+					if weight <= 0.0 || self.edited_splats.contains_key(&index) || new_splats.contains_key(&index) { continue; }
+					new_splats.insert(index, redistribute(&self.splat_weights[index], self.splat_channels, channel as usize, strength * weight));
 				}
 			}
 		}
+
+		for index in new_splats.keys() {
+			self.edited_splats.insert(*index, self.splat_weights[*index]);
+		}
+		let mut dirty_chunks:HashSet<usize> = HashSet::new();
+		self.chunks_at_seam.clear();
+		self.write_splats(&new_splats, &mut dirty_chunks);
+		self.remesh_chunks(_owner, &dirty_chunks);
+	}
+
+	#[export]
+	pub fn get_splat_weights (&self, _owner:&Resource, global_x:i32, global_z:i32) -> TypedArray<i32> {
+		let mut weights:TypedArray<i32> = TypedArray::new();
+		if self.splat_channels > 0 {
+			let cell_weights = self.splat_weights_at(global_x, global_z);
+			for i in 0..self.splat_channels {
+				weights.push(cell_weights[i] as i32);
+			}
+		}
+		return weights;
+	}
+
+	// All weights, `splat_channels` bytes per cell in the order of the height array
+	#[export]
+	pub fn get_splat_data (&self, _owner:&Resource) -> TypedArray<u8> {
+		let mut data:Vec<u8> = Vec::with_capacity(self.splat_weights.len() * self.splat_channels);
+		for weights in self.splat_weights.iter() {
+			data.extend_from_slice(&weights[..self.splat_channels]);
+		}
+		return TypedArray::from_vec(data);
+	}
+
+	// Replaces every weight with data laid out like get_splat_data. Returns false when the size does not match
+	#[export]
+	pub fn set_splat_data (&mut self, _owner:&Resource, channels:i64, data:TypedArray<u8>) -> bool {
+		if !self.load_splat_data(channels, data) {
+			return false;
+		}
+		self.undo_stack.clear();
+		self.redo_stack.clear();
+		if self.simplify_flat {
+			self.update_all_chunks(_owner);
+		}
 		self.generate_all_meshes(_owner);
+		return true;
+	}
+
+	fn load_splat_data (&mut self, channels:i64, data:TypedArray<u8>) -> bool {
+		let channels = (channels.max(0) as usize).min(MAX_SPLAT_CHANNELS);
+		if channels == 0 || data.len() as usize != self.map_width * self.map_depth * channels {
+			return false;
+		}
+		let data = data.read();
+		self.splat_weights = data.chunks(channels).map(|cell| {
+			let mut weights:SplatWeights = [0; MAX_SPLAT_CHANNELS];
+			weights[..channels].copy_from_slice(cell);
+			normalize(&weights, channels)
+		}).collect();
+		self.splat_channels = channels;
+		self.edited_splats.clear();
+		return true;
+	}
+
+	// One pixel per cell holding the weights of materials 4 * layer to 4 * layer + 3 as RGBA
+	#[export]
+	pub fn get_splat_texture (&self, _owner:&Resource, layer:i64) -> Ref<Image,Unique> {
+		let image = Image::new();
+		image.create(self.map_width as i64, self.map_depth as i64, false, Image::FORMAT_RGBA8);
+		if self.splat_channels > 0 {
+			let layer = layer.max(0) as usize;
+			image.lock();
+			for z in 0..self.map_depth {
+				for x in 0..self.map_width {
+					image.set_pixel(x as i64, z as i64, pack_layer(&self.splat_weights[z * self.map_width + x], layer));
+				}
+			}
+			image.unlock();
+		}
+		return image;
 	}
 
-	pub fn replace_color (&mut self, _owner:&Resource, old_color:Color, similarity:f32, new_color:Color, strength:f32, blend_mode:i32) {
+	// Recolors every cell within `similarity` (0 to 1, see Color8::similarity) of old_color. Honours the selection
+	#[export]
+	pub fn replace_color (&mut self, _owner:&Resource, old_color:Color, similarity:f32, new_color:Color, strength:f32, blend_mode:i32) -> bool {
 		let old_color = Color8::from_color(old_color);
 		let new_color = Color8::from_color(new_color);
-		let mut changed_verts:HashMap<Vector2Key,Color8> = HashMap::new();
-		let threshold = similarity * similarity;
-		for (vert_key, color_index) in self.vertex_colors.iter() {
-			let color = self.color_list[*color_index];
-			if !changed_verts.contains_key(vert_key) {
-				let sq_similarity:f32 = color.squared_similarity(old_color);
-				if sq_similarity >= threshold {
-					let new_color:Color8 = color.blend(new_color, strength, blend_mode);
-					changed_verts.insert(*vert_key, new_color);
+		let blend_mode = BlendMode::from_i32(blend_mode);
+
+		// Cells share palette entries, so each entry only needs comparing once
+		let matching:Vec<bool> = self.color_list.iter().map(|color| color.similarity(old_color) >= similarity).collect::<Vec<bool>>();
+
+		let mut new_colors:HashMap<usize,Color8> = HashMap::new();
+		for index in 0..self.vertex_colors.len() {
+			let color_index = self.vertex_colors[index];
+			if !matching[color_index] { continue; }
+			let weight = self.selection_weight(index);
+			if weight <= 0.0 { continue; }
+
+			let color = self.color_list[color_index];
+			let blended = color.blend(new_color, strength * weight, blend_mode);
+			if blended != color {
+				new_colors.insert(index, blended);
+			}
+		}
+		return self.apply_global_edits(_owner, &HashMap::new(), &new_colors);
+	}
+
+	// Paints the region connected to `position`. match_mode: 0 color within `tolerance` (0 to 1) of the start cell,
+	// 1 same height, 2 height within `tolerance` of the start cell. With use_selection, unselected cells stop the fill
+	#[export]
+	pub fn flood_fill (&mut self, _owner:&Resource, position:Vector2, color:Color, strength:f32, blend_mode:i32, match_mode:i32, tolerance:f32, diagonal:bool, use_selection:bool) -> bool {
+		let start_x = position.x.floor() as i32;
+		let start_z = position.y.floor() as i32;
+		if start_x < 0 || start_z < 0 || start_x >= self.map_width as i32 || start_z >= self.map_depth as i32 {
+			return false;
+		}
+
+		let start_index = start_z as usize * self.map_width + start_x as usize;
+		let start_color = self.color_list[self.vertex_colors[start_index]];
+		let start_height = self.heights[start_index];
+		let color_matches:Vec<bool> = self.color_list.iter().map(|c| c.similarity(start_color) >= 1.0 - tolerance).collect::<Vec<bool>>();
+
+		let matches = |index:usize| -> bool {
+			match match_mode {
+				1 => self.heights[index] == start_height,
+				2 => ((self.heights[index] - start_height).abs() as f32) <= tolerance,
+				_ => color_matches[self.vertex_colors[index]]
+			}
+		};
+		let weight_at = |index:usize| -> f32 {
+			return if use_selection { self.selection_weight(index) } else { 1.0 };
+		};
+
+		let neighbours:&[(i32,i32)] = if diagonal {
+			&[(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)]
+		}
+		else {
+			&[(1, 0), (-1, 0), (0, 1), (0, -1)]
+		};
+
+		let color8 = Color8::from_color(color);
+		let blend_mode = BlendMode::from_i32(blend_mode);
+		let mut new_colors:HashMap<usize,Color8> = HashMap::new();
+		let mut visited:Vec<bool> = vec![false; self.heights.len()];
+		let mut queue:VecDeque<(i32,i32)> = VecDeque::new();
+		if weight_at(start_index) > 0.0 {
+			visited[start_index] = true;
+			queue.push_back((start_x, start_z));
+		}
+
+		while let Some((x, z)) = queue.pop_front() {
+			let index = z as usize * self.map_width + x as usize;
+			let current_color = self.color_list[self.vertex_colors[index]];
+			new_colors.insert(index, current_color.blend(color8, strength * weight_at(index), blend_mode));
+
+			for (dx, dz) in neighbours.iter() {
+				let (nx, nz) = (x + dx, z + dz);
+				if nx < 0 || nz < 0 || nx >= self.map_width as i32 || nz >= self.map_depth as i32 { continue; }
+				let neighbour = nz as usize * self.map_width + nx as usize;
+				if visited[neighbour] { continue; }
+				visited[neighbour] = true;
+				if weight_at(neighbour) > 0.0 && matches(neighbour) {
+					queue.push_back((nx, nz));
 				}
 			}
 		}
-		for (vert_key, color) in changed_verts.iter() {
-			self.set_vertex_color(*vert_key, *color);
+
+		return self.apply_terrain_edits(_owner, &HashMap::new(), &new_colors);
+	}
+
+	// Linear gradient from start to end, or radial outwards from start with end on the outer edge
+	#[export]
+	pub fn gradient_fill (&mut self, _owner:&Resource, start:Vector2, end:Vector2, radial:bool, offsets:TypedArray<f32>, colors:TypedArray<Color>, strength:f32, blend_mode:i32) -> bool {
+		let gradient = ColorGradient::new(&offsets, &colors);
+		let dir = end - start;
+		let length_squared = dir.square_length();
+		if gradient.is_empty() || length_squared <= 0.0 {
+			return false;
 		}
-		self.generate_all_meshes(_owner);
-	}*/
+
+		let new_colors = self.gradient_colors(&gradient, strength, BlendMode::from_i32(blend_mode), |x, z| {
+			let point = Vector2::new(x as f32, z as f32);
+			if radial {
+				return point.distance_to(start) / length_squared.sqrt();
+			}
+			return (point - start).dot(dir) / length_squared;
+		});
+		return self.apply_global_edits(_owner, &HashMap::new(), &new_colors);
+	}
+
+	// Colors each cell by where its height falls between min_height and max_height
+	#[export]
+	pub fn height_gradient_fill (&mut self, _owner:&Resource, min_height:i32, max_height:i32, offsets:TypedArray<f32>, colors:TypedArray<Color>, strength:f32, blend_mode:i32) -> bool {
+		let gradient = ColorGradient::new(&offsets, &colors);
+		if gradient.is_empty() || max_height == min_height {
+			return false;
+		}
+
+		let new_colors = self.gradient_colors(&gradient, strength, BlendMode::from_i32(blend_mode), |x, z| {
+			return (self.heights[z * self.map_width + x] - min_height) as f32 / (max_height - min_height) as f32;
+		});
+		return self.apply_global_edits(_owner, &HashMap::new(), &new_colors);
+	}
+
+	// Blends the gradient over every selected cell, with t_at giving each cell's position along it
+	fn gradient_colors<F:Fn(usize, usize) -> f32> (&self, gradient:&ColorGradient, strength:f32, blend_mode:BlendMode, t_at:F) -> HashMap<usize,Color8> {
+		let mut new_colors:HashMap<usize,Color8> = HashMap::new();
+		for z in 0..self.map_depth {
+			for x in 0..self.map_width {
+				let index = z * self.map_width + x;
+				let weight = self.selection_weight(index);
+				if weight <= 0.0 { continue; }
+
+				let current_color = self.color_list[self.vertex_colors[index]];
+				let new_color = current_color.blend(gradient.sample(t_at(x, z).clamp(0.0, 1.0)), strength * weight, blend_mode);
+				if new_color != current_color {
+					new_colors.insert(index, new_color);
+				}
+			}
+		}
+		return new_colors;
+	}
 
 	#[export]
 	pub fn draw_at (&mut self,_owner:&Resource, global_x:i32, global_z:i32, brush_size:Vector2, brush_mode:i32, button_index:i32, is_height_locked:bool, locked_height:i32) -> bool {
@@ -650,6 +1345,23 @@ impl TerrainUtils {
 		}
 
 		let mut chunks_to_update:HashSet<i32> = HashSet::new();
+		let locked_height:Option<i32> = if is_height_locked { Some(locked_height) } else { None };
+
+		// Mirrored dabs share the stroke's edited positions, so they land in the same undo action
+		for dab in self.symmetric_positions(Vector2::new(global_x as f32, global_z as f32)) {
+			self.draw_dab(_owner, dab.x.round() as i32, dab.y.round() as i32, brush_size, brush_mode, button_index, locked_height, &mut chunks_to_update);
+		}
+
+		let chunk_ids:Vec<usize> = chunks_to_update.iter().filter(|chunk_id| **chunk_id > -1).map(|chunk_id| *chunk_id as usize).collect();
+		for chunk_id in chunk_ids.iter() {
+			self.chunks_at_seam.remove(chunk_id);
+		}
+		self.rebuild_chunks(&chunk_ids);
+
+		return chunk_ids.len() > 0;
+	}
+
+	fn draw_dab (&mut self, _owner:&Resource, global_x:i32, global_z:i32, brush_size:Vector2, brush_mode:i32, button_index:i32, locked_height:Option<i32>, chunks_to_update:&mut HashSet<i32>) {
 		let brush_height = brush_size.y as i32;
 
 		if brush_mode == 5 && self.in_bounds(_owner, global_x, global_z)  {
@@ -657,7 +1369,7 @@ impl TerrainUtils {
 			let num_positions_in_range:usize = positions_in_range.len();
 			let mut target_height:f32 = 0.0;
 			
-			if is_height_locked {
+			if locked_height.is_some() {
 				target_height = self.height(global_x as i32, global_z as i32) as f32;
 			}
 			else {
@@ -677,17 +1389,17 @@ impl TerrainUtils {
 				for (x,y,z) in positions_in_range.iter() {
 					if *y != target_height.round() as i32 && (*z as f32 - global_z as f32).powf(2.0) + (*x as f32 - global_x as f32).powf(2.0) <= 0.25 * brush_size.x.powf(2.0){
 						let sign:i32 = if *y < target_height.round() as i32 { -1 } else { 1 };
+						let index = *z as usize * self.map_width + *x as usize;
 
-						let new_height = *y - sign;
+						let new_height = self.clamp_height(*y - (sign as f32 * self.selection_weight(index)).round() as i32);
 						if *y != new_height {
-							let index = *z as usize * self.map_size + *x as usize;
 							if !self.edited_positions.contains_key(&index) {
 								self.edited_positions.insert(index, *y);
 							}
 						
 							self.set_height_at(*x, *z, new_height);
 							//self.heights[index as usize] = new_height;
-							let chunk_id:i32 = ((z / self.chunk_size as i32) * self.num_chunks as i32 + (x / self.chunk_size as i32)) as i32;
+							let chunk_id:i32 = self.chunk_id_at(*x as usize, *z as usize) as i32;
 							self.check_seam_condition(*x as i32, *z as i32);  
 							chunks_to_update.insert(chunk_id);
 						}
@@ -698,73 +1410,187 @@ impl TerrainUtils {
 		else if brush_mode != 5 {
 			let positions_in_range:Vec<(i32,i32,i32)> = self.get_grid_positions_in_radius(global_x, global_z, brush_size.x as i32);
 			for (x,y,z) in positions_in_range.iter() {
-				let chunk_id = self.draw_height_at(*x as i32, *z as i32, brush_mode, brush_height, button_index, is_height_locked, locked_height);
+				let chunk_id = self.draw_height_at(*x as i32, *z as i32, brush_mode, brush_height, button_index, locked_height);
 				chunks_to_update.insert(chunk_id);
 			}
 		}
+	}
+
+	// Sets the point the next clone stroke samples from. The offset to the brush is fixed when the stroke starts
+	#[export]
+	pub fn set_clone_source (&mut self, _owner:&Resource, position:Vector2) {
+		self.clone_source = Some(Vector2::new(position.x.round(), position.y.round()));
+		self.clone_offset = None;
+	}
 
+	#[export]
+	pub fn set_clone_offset (&mut self, _owner:&Resource, offset:Vector2) {
+		self.clone_offset = Some(Vector2::new(offset.x.round(), offset.y.round()));
+	}
 
-		let mut do_update_mesh:bool = false;
-		for chunk_id in chunks_to_update.iter() {
-			if *chunk_id > -1 {
-				self.update_chunk(_owner, *chunk_id as usize);
-				self.generate_chunk_mesh(_owner, *chunk_id as usize);
-				self.chunks_at_seam.remove(&(*chunk_id as usize));
-				do_update_mesh = true;
+	// Samples from another terrain, given in the same layout as the saved file, instead of this one
+	#[export]
+	pub fn set_clone_source_terrain (&mut self, _owner:&Resource, map_width:i64, map_depth:i64, heights:TypedArray<i32>, color_list:TypedArray<Color>, vertex_colors:TypedArray<i32>) -> bool {
+		let map_width = map_width.max(0) as usize;
+		let map_depth = map_depth.max(0) as usize;
+		if heights.len() as usize != map_width * map_depth || vertex_colors.len() as usize != map_width * map_depth {
+			return false;
+		}
+		let mut region = TerrainRegion::new(map_width, map_depth);
+		for i in 0..map_width * map_depth {
+			let color_index = vertex_colors.get(i as i32);
+			region.heights[i] = heights.get(i as i32);
+			if color_index > 0 && color_index < color_list.len() {
+				region.colors[i] = Color8::from_color(color_list.get(color_index));
 			}
+			region.weights[i] = 1.0;
 		}
+		self.clone_source_terrain = Some(region);
+		return true;
+	}
 
-		return do_update_mesh;
+	#[export]
+	pub fn clear_clone_source_terrain (&mut self, _owner:&Resource) {
+		self.clone_source_terrain = None;
 	}
 
-	fn draw_height_at (&mut self, global_x:i32, global_z:i32, draw_mode:i32, brush_height:i32, button_index:i32, is_height_locked:bool, locked_height:i32) -> i32 {
-		if global_x < 0 || global_z < 0 || global_x >= self.map_size as i32 || global_z >= self.map_size as i32 {
-			return -1;
+	// Height and color at a source cell as they were before the current stroke
+	fn clone_sample (&self, source_x:i32, source_z:i32) -> Option<(i32, Color8)> {
+		if let Some(region) = &self.clone_source_terrain {
+			if source_x < 0 || source_z < 0 || source_x >= region.size_x as i32 || source_z >= region.size_z as i32 {
+				return None;
+			}
+			let index = source_z as usize * region.size_x + source_x as usize;
+			return Some((region.heights[index], region.colors[index]));
 		}
 
-		let i:usize = global_z as usize * self.map_size + global_x as usize;
-		if self.edited_positions.contains_key(&i) {
-			return -1;
+		if source_x < 0 || source_z < 0 || source_x >= self.map_width as i32 || source_z >= self.map_depth as i32 {
+			return None;
+		}
+		let index = source_z as usize * self.map_width + source_x as usize;
+		let height = self.edited_positions.get(&index).cloned().unwrap_or(self.heights[index]);
+		let color = self.edited_colors.get(&index).cloned().unwrap_or(self.color_list[self.vertex_colors[index]]);
+		return Some((height, color));
+	}
+
+	// clone_mode: 0 heights, 1 colors, 2 both. Falloff is the fraction of the radius that fades out
+	#[export]
+	pub fn clone_at (&mut self, _owner:&Resource, global_x:i32, global_z:i32, brush_size:f32, clone_mode:i32, strength:f32, falloff:f32) -> bool {
+		if !self.is_drawing {
+			self.is_drawing = true;
+			self.edited_positions.clear();
+			self.edited_colors.clear();
+			self.redo_stack.clear();
+			if self.clone_offset.is_none() {
+				if let Some(source) = self.clone_source {
+					self.clone_offset = Some(source - Vector2::new(global_x as f32, global_z as f32));
+				}
+			}
+		}
+
+		let offset = match self.clone_offset {
+			Some(offset) => offset,
+			None => return false
+		};
+
+		let clone_heights = clone_mode == 0 || clone_mode == 2;
+		let clone_colors = clone_mode == 1 || clone_mode == 2;
+		let radius = (brush_size * 0.5).max(0.5);
+		let inner_radius = radius * (1.0 - falloff.clamp(0.0, 1.0));
+
+		let mut geometry_chunks:HashSet<usize> = HashSet::new();
+		let mut color_chunks:HashSet<usize> = HashSet::new();
+
+		for (x,y,z) in self.get_grid_positions_in_radius(global_x, global_z, brush_size as i32).iter() {
+			let index = *z as usize * self.map_width + *x as usize;
+			let dist = Vector2::new((*x - global_x) as f32, (*z - global_z) as f32).length();
+			let mut weight:f32 = if dist <= inner_radius { 1.0 } else { 1.0 - (dist - inner_radius) / (radius - inner_radius) };
+			weight = weight.clamp(0.0, 1.0) * strength * self.selection_weight(index);
+			if weight <= 0.0 { continue; }
+
+			let (source_height, source_color) = match self.clone_sample(*x + offset.x as i32, *z + offset.y as i32) {
+				Some(sample) => sample,
+				None => continue
+			};
+
+			// Each cell is only sampled once per stroke
+			if clone_heights && !self.edited_positions.contains_key(&index) {
+				self.edited_positions.insert(index, *y);
+				let new_height = self.clamp_height(*y + ((source_height - *y) as f32 * weight).round() as i32);
+				if new_height != *y {
+					self.set_height_at(*x, *z, new_height);
+					self.check_seam_condition(*x, *z);
+					geometry_chunks.insert(self.chunk_id_at(*x as usize, *z as usize));
+				}
+			}
+
+			if clone_colors && !self.edited_colors.contains_key(&index) {
+				let current_color = self.color_list[self.vertex_colors[index]];
+				self.edited_colors.insert(index, current_color);
+				let new_color = current_color.mix(source_color, weight);
+				if new_color != current_color {
+					self.set_vertex_color(*x, *z, new_color);
+					self.check_seam_condition(*x, *z);
+					color_chunks.insert(self.chunk_id_at(*x as usize, *z as usize));
+				}
+			}
+		}
+
+		for id in geometry_chunks.iter() {
+			self.chunks_at_seam.remove(id);
+		}
+		self.rebuild_chunks(&geometry_chunks.union(&color_chunks).cloned().collect::<Vec<usize>>());
+
+		return geometry_chunks.len() > 0 || color_chunks.len() > 0;
+	}
+
+	fn draw_height_at (&mut self, global_x:i32, global_z:i32, draw_mode:i32, brush_height:i32, button_index:i32, locked_height:Option<i32>) -> i32 {
+		if global_x < 0 || global_z < 0 || global_x >= self.map_width as i32 || global_z >= self.map_depth as i32 {
+			return -1;
+		}
+
+		let i:usize = global_z as usize * self.map_width + global_x as usize;
+		if self.edited_positions.contains_key(&i) {
+			return -1;
 		}
 
 		let current_height = self.height(global_x as i32, global_z as i32);
 		let mut new_height = current_height;
 
-		if (draw_mode == 0 || draw_mode == 1) &&
-			((current_height == locked_height && is_height_locked) || !is_height_locked) {
+		if (draw_mode == 0 || draw_mode == 1) && locked_height.map_or(true, |locked| current_height == locked) {
 				if (draw_mode == 0 && button_index == 1) || (draw_mode == 1 && button_index == 2) {
 					new_height = current_height + brush_height as i32;
 				}
 				else if (draw_mode == 1 && button_index == 1) || (draw_mode == 0 && button_index == 2) {
-					new_height = (current_height - brush_height as i32).max(0);
+					new_height = current_height - brush_height as i32;
 				}
 		}
 		else if draw_mode == 2 {
-			if is_height_locked && current_height == locked_height {
-				new_height = current_height + brush_height as i32;
-			}
-			else if !is_height_locked {
-				new_height = brush_height as i32;
+			match locked_height {
+				Some(locked) if current_height == locked => new_height = current_height + brush_height as i32,
+				Some(_) => {}
+				None => new_height = brush_height as i32
 			}
 		}
 
 		else if draw_mode == 3 {
-			if is_height_locked && current_height < locked_height + brush_height as i32 {
-				new_height = locked_height + brush_height as i32;
-			}
-			else if !is_height_locked && current_height < brush_height as i32 {
-				new_height = brush_height as i32;
+			let target_height = locked_height.unwrap_or(0) + brush_height as i32;
+			if current_height < target_height {
+				new_height = target_height;
 			}
 		}
 		else if draw_mode == 4 {
-			if is_height_locked && current_height > locked_height + brush_height as i32 {
-				new_height = locked_height + brush_height as i32;
-			}
-			else if !is_height_locked && current_height > brush_height as i32 {
-				new_height = brush_height as i32;
+			let target_height = locked_height.unwrap_or(0) + brush_height as i32;
+			if current_height > target_height {
+				new_height = target_height;
 			}
 		}
 
+		new_height = self.clamp_height(new_height);
+
+		// Scale the change by the selection so feathered edges blend into the surrounding terrain
+		new_height = current_height + ((new_height - current_height) as f32 * self.selection_weight(i)).round() as i32;
+
 		if new_height != current_height {
 			self.edited_positions.insert(i, current_height);
 			self.set_height_at(global_x as i32, global_z as i32, new_height);
@@ -774,7 +1600,7 @@ impl TerrainUtils {
 
 			self.check_seam_condition(global_x, global_z);
 			
-			return (chunk_row * self.num_chunks as i32 + chunk_col) as i32;
+			return (chunk_row * self.num_chunks_x as i32 + chunk_col) as i32;
 		}
 
 		return -1;
@@ -785,121 +1611,889 @@ impl TerrainUtils {
 		let global_z = global_z as usize;
 
 		let chunk_size:usize = self.chunk_size;
-		let num_chunks = self.num_chunks;
+		let num_chunks_x = self.num_chunks_x;
 
 		let chunk_col = global_x as usize / chunk_size;
 		let chunk_row = global_z as usize / chunk_size;
 
 		let x_border_min = global_x % chunk_size == 0 && chunk_col > 0;
-		let x_border_max = global_x % chunk_size == 1 && chunk_col < num_chunks - 1;
+		let x_border_max = global_x % chunk_size == 1 && chunk_col < num_chunks_x - 1;
 
 		let z_border_min = global_z % chunk_size == 0 && chunk_row > 0;
-		let z_border_max = global_z % chunk_size == 1  && chunk_row < num_chunks - 1;
+		let z_border_max = global_z % chunk_size == 1  && chunk_row < self.num_chunks_z - 1;
 
 
 		if x_border_min {
 			if z_border_min {
-				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks + chunk_col - 1);
-				self.chunks_at_seam.insert(chunk_row * num_chunks  + chunk_col - 1);
-				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks  + chunk_col);
+				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks_x + chunk_col - 1);
+				self.chunks_at_seam.insert(chunk_row * num_chunks_x  + chunk_col - 1);
+				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks_x  + chunk_col);
 			}
 			else if z_border_max {
-				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks + chunk_col - 1);
-				self.chunks_at_seam.insert(chunk_row * num_chunks + chunk_col - 1);
-				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks + chunk_col);
+				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks_x + chunk_col - 1);
+				self.chunks_at_seam.insert(chunk_row * num_chunks_x + chunk_col - 1);
+				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks_x + chunk_col);
 			}
 			else {
-				self.chunks_at_seam.insert(chunk_row * num_chunks + chunk_col - 1);
+				self.chunks_at_seam.insert(chunk_row * num_chunks_x + chunk_col - 1);
 			}
 		}
 		else if x_border_max {
 			if z_border_min {
-				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks + chunk_col + 1);
-				self.chunks_at_seam.insert(chunk_row * num_chunks + chunk_col + 1);
-				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks + chunk_col);
+				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks_x + chunk_col + 1);
+				self.chunks_at_seam.insert(chunk_row * num_chunks_x + chunk_col + 1);
+				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks_x + chunk_col);
 			}
 			else if z_border_max {
-				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks + chunk_col + 1);
-				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks + chunk_col);
-				self.chunks_at_seam.insert(chunk_row * num_chunks + chunk_col + 1);
+				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks_x + chunk_col + 1);
+				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks_x + chunk_col);
+				self.chunks_at_seam.insert(chunk_row * num_chunks_x + chunk_col + 1);
 			}
 			else {
-				self.chunks_at_seam.insert(chunk_row * num_chunks + chunk_col + 1);
+				self.chunks_at_seam.insert(chunk_row * num_chunks_x + chunk_col + 1);
 			}
 		}
 		else {
 			if z_border_min {
-				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks + chunk_col);
+				self.chunks_at_seam.insert((chunk_row - 1) * num_chunks_x + chunk_col);
 			}
 			else if z_border_max {
-				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks + chunk_col);
+				self.chunks_at_seam.insert((chunk_row + 1) * num_chunks_x + chunk_col);
+			}
+		}
+	}
+
+	fn chunk_id_at (&self, global_x:usize, global_z:usize) -> usize {
+		return (global_z / self.chunk_size) * self.num_chunks_x + (global_x / self.chunk_size);
+	}
+
+	fn chunk_count (&self) -> usize {
+		return self.num_chunks_x * self.num_chunks_z;
+	}
+
+	// Writes heights without remeshing. Returns the previous heights of the changed cells
+	fn write_heights (&mut self, new_heights:&HashMap<usize,i32>, dirty_chunks:&mut HashSet<usize>) -> HashMap<usize,i32> {
+		let mut prev_heights:HashMap<usize,i32> = HashMap::new();
+		for (index,height) in new_heights.iter() {
+			let height = self.clamp_height(*height);
+			let current_height = self.height_by_index(*index);
+			if current_height == height { continue; }
+			prev_heights.insert(*index, current_height);
+
+			let x = index % self.map_width;
+			let z = index / self.map_width;
+			self.set_height_by_index(*index, height);
+			self.check_seam_condition(x as i32, z as i32);
+			dirty_chunks.insert(self.chunk_id_at(x, z));
+		}
+		return prev_heights;
+	}
+
+	// Writes colors without remeshing. Returns the previous colors of the changed cells
+	fn write_colors (&mut self, new_colors:&HashMap<usize,Color8>, dirty_chunks:&mut HashSet<usize>) -> HashMap<usize,Color8> {
+		let mut prev_colors:HashMap<usize,Color8> = HashMap::new();
+		for (index,color) in new_colors.iter() {
+			let current_color = self.color_list[self.vertex_colors[*index]];
+			if current_color == *color { continue; }
+			prev_colors.insert(*index, current_color);
+
+			let x = index % self.map_width;
+			let z = index / self.map_width;
+			self.set_vertex_color(x as i32, z as i32, *color);
+			self.check_seam_condition(x as i32, z as i32);
+			dirty_chunks.insert(self.chunk_id_at(x, z));
+		}
+		return prev_colors;
+	}
+
+	// Writes splat weights without remeshing. Returns the previous weights of the changed cells
+	fn write_splats (&mut self, new_splats:&HashMap<usize,SplatWeights>, dirty_chunks:&mut HashSet<usize>) -> HashMap<usize,SplatWeights> {
+		let mut prev_splats:HashMap<usize,SplatWeights> = HashMap::new();
+		if self.splat_channels == 0 {
+			return prev_splats;
+		}
+		for (index,weights) in new_splats.iter() {
+			let current_weights = self.splat_weights[*index];
+			if current_weights == *weights { continue; }
+			prev_splats.insert(*index, current_weights);
+
+			let x = index % self.map_width;
+			let z = index / self.map_width;
+			self.splat_weights[*index] = *weights;
+			self.check_seam_condition(x as i32, z as i32);
+			dirty_chunks.insert(self.chunk_id_at(x, z));
+		}
+		return prev_splats;
+	}
+
+	// Remeshes the dirty chunks along with any chunks flagged at a seam
+	fn remesh_chunks (&mut self, _owner:&Resource, dirty_chunks:&HashSet<usize>) {
+		let chunk_ids = dirty_chunks.union(&self.chunks_at_seam).cloned().collect::<Vec<usize>>();
+		self.rebuild_chunks(&chunk_ids);
+		self.chunks_at_seam.clear();
+	}
+
+	fn push_undo (&mut self, action:Action) -> bool {
+		let is_empty = match &action {
+			Action::TerrainEdit(data) => data.len() == 0,
+			Action::VertexColor(data) => data.len() == 0,
+			Action::SplatWeights(data) => data.len() == 0,
+			Action::SplatChannels(..) => false,
+			Action::Rotate(quarter_turns) => quarter_turns % 4 == 0,
+			Action::Flip(_) => false,
+			Action::Batch(actions) => actions.len() == 0,
+		};
+		if is_empty {
+			return false;
+		}
+		self.undo_stack.push(action);
+		self.redo_stack.clear();
+		return true;
+	}
+
+	// Sets many heights at once and records them as a single undo step
+	fn apply_height_edits (&mut self, owner:&Resource, new_heights:&HashMap<usize,i32>) -> bool {
+		let mut dirty_chunks:HashSet<usize> = HashSet::new();
+		self.chunks_at_seam.clear();
+		let prev_heights = self.write_heights(new_heights, &mut dirty_chunks);
+		self.remesh_chunks(owner, &dirty_chunks);
+		return self.push_undo(Action::TerrainEdit(prev_heights));
+	}
+
+	// Sets heights and colors together and records them as a single undo step
+	fn apply_terrain_edits (&mut self, owner:&Resource, new_heights:&HashMap<usize,i32>, new_colors:&HashMap<usize,Color8>) -> bool {
+		let mut dirty_chunks:HashSet<usize> = HashSet::new();
+		self.chunks_at_seam.clear();
+		let prev_heights = self.write_heights(new_heights, &mut dirty_chunks);
+		let prev_colors = self.write_colors(new_colors, &mut dirty_chunks);
+		self.remesh_chunks(owner, &dirty_chunks);
+
+		let mut actions:Vec<Action> = Vec::new();
+		if prev_heights.len() > 0 {
+			actions.push(Action::TerrainEdit(prev_heights));
+		}
+		if prev_colors.len() > 0 {
+			actions.push(Action::VertexColor(prev_colors));
+		}
+		if actions.len() == 1 {
+			return self.push_undo(actions.pop().unwrap());
+		}
+		return self.push_undo(Action::Batch(actions));
+	}
+
+	// Like apply_terrain_edits, but rebuilds every chunk once instead of tracking seams
+	fn apply_global_edits (&mut self, owner:&Resource, new_heights:&HashMap<usize,i32>, new_colors:&HashMap<usize,Color8>) -> bool {
+		let mut dirty_chunks:HashSet<usize> = HashSet::new();
+		let prev_heights = self.write_heights(new_heights, &mut dirty_chunks);
+		let prev_colors = self.write_colors(new_colors, &mut dirty_chunks);
+		self.chunks_at_seam.clear();
+		if dirty_chunks.len() == 0 {
+			return false;
+		}
+		self.update_all_chunks(owner);
+		self.generate_all_meshes(owner);
+		self.update_terrain_arrays();
+
+		let mut actions:Vec<Action> = Vec::new();
+		if prev_heights.len() > 0 {
+			actions.push(Action::TerrainEdit(prev_heights));
+		}
+		if prev_colors.len() > 0 {
+			actions.push(Action::VertexColor(prev_colors));
+		}
+		if actions.len() == 1 {
+			return self.push_undo(actions.pop().unwrap());
+		}
+		return self.push_undo(Action::Batch(actions));
+	}
+
+	fn apply_global_height_edits (&mut self, owner:&Resource, new_heights:&HashMap<usize,i32>) -> bool {
+		return self.apply_global_edits(owner, new_heights, &HashMap::new());
+	}
+
+	// Applies a per-cell height function everywhere the selection allows
+	fn map_heights<F:Fn(i32) -> i32> (&mut self, owner:&Resource, height_fn:F) -> bool {
+		let mut new_heights:HashMap<usize,i32> = HashMap::new();
+		for i in 0..self.heights.len() {
+			let weight = self.selection_weight(i);
+			if weight <= 0.0 { continue; }
+			let current_height = self.heights[i];
+			let new_height = current_height + ((height_fn(current_height) - current_height) as f32 * weight).round() as i32;
+			if new_height != current_height {
+				new_heights.insert(i, new_height);
 			}
 		}
+		return self.apply_global_height_edits(owner, &new_heights);
+	}
+
+	// Moves every cell's height, color and splat weights to a new position along with the splines. source_of maps
+	// a destination cell to the cell it comes from and move_point does the same move for a spline point.
+	// Records no undo step, the callers record the inverse move instead
+	fn remap_cells<F, P> (&mut self, owner:&Resource, source_of:F, move_point:P) -> bool
+	where F:Fn(usize, usize) -> (usize, usize), P:Fn(Vector2) -> Vector2 {
+		let mut new_heights:HashMap<usize,i32> = HashMap::new();
+		let mut new_colors:HashMap<usize,Color8> = HashMap::new();
+		let mut new_splats:HashMap<usize,SplatWeights> = HashMap::new();
+		for z in 0..self.map_depth {
+			for x in 0..self.map_width {
+				let (source_x, source_z) = source_of(x, z);
+				let index = z * self.map_width + x;
+				let source_index = source_z * self.map_width + source_x;
+				new_heights.insert(index, self.heights[source_index]);
+				new_colors.insert(index, self.color_list[self.vertex_colors[source_index]]);
+				if self.splat_channels > 0 {
+					new_splats.insert(index, self.splat_weights[source_index]);
+				}
+			}
+		}
+
+		let mut dirty_chunks:HashSet<usize> = HashSet::new();
+		self.write_splats(&new_splats, &mut dirty_chunks);
+		self.write_heights(&new_heights, &mut dirty_chunks);
+		self.write_colors(&new_colors, &mut dirty_chunks);
+		self.chunks_at_seam.clear();
+		for spline in self.splines.iter_mut() {
+			for point in spline.points.iter_mut() {
+				point.position = move_point(point.position);
+			}
+		}
+		if dirty_chunks.len() == 0 && self.splines.len() == 0 {
+			return false;
+		}
+		self.update_all_chunks(owner);
+		self.generate_all_meshes(owner);
+		self.update_terrain_arrays();
+		return true;
+	}
+
+	#[export]
+	pub fn invert_heights (&mut self, _owner:&Resource, pivot:i32) -> bool {
+		return self.map_heights(_owner, |height| 2 * pivot - height);
 	}
 
 	#[export]
-	fn resize_terrain (&mut self, _owner:&Resource, new_chunk_size:i32, new_num_chunks:i32, x_move:i32, z_move:i32) {
-		let new_map_size:i32 = new_chunk_size * new_num_chunks;
-		let diff:i32 = new_map_size as i32 - self.map_size as i32;
-		if diff == 0 { return; }
+	pub fn scale_heights (&mut self, _owner:&Resource, factor:f32, pivot:i32) -> bool {
+		return self.map_heights(_owner, |height| pivot + ((height - pivot) as f32 * factor).round() as i32);
+	}
+
+	#[export]
+	pub fn offset_heights (&mut self, _owner:&Resource, amount:i32) -> bool {
+		return self.map_heights(_owner, |height| height + amount);
+	}
 
-		let half_size:i32 = ((diff as f32) / 2.0).trunc() as i32;
+	#[export]
+	pub fn clamp_heights (&mut self, _owner:&Resource, min_height:i32, max_height:i32) -> bool {
+		let (min_height, max_height) = (min_height.min(max_height), min_height.max(max_height));
+		return self.map_heights(_owner, |height| height.clamp(min_height, max_height));
+	}
 
-		let offset_x:i32 = match x_move {
-			0 => 0,
-			1 => half_size,
-			2 => diff,
-			_ => 0
+	// Rotates the whole map clockwise by 90 degree steps. Quarter turns on a rectangular map swap the width
+	// and depth, so the chunk grid changes shape and the chunk nodes have to be laid out again from
+	// get_num_chunks_x and get_num_chunks_z, as after a resize. Either way the turn is one undo step
+	#[export]
+	pub fn rotate_map (&mut self, _owner:&Resource, quarter_turns:i32) -> bool {
+		let quarter_turns = quarter_turns.rem_euclid(4);
+		if !self.rotate_cells(_owner, quarter_turns) {
+			return false;
+		}
+		return self.push_undo(Action::Rotate(4 - quarter_turns));
+	}
+
+	fn rotate_cells (&mut self, owner:&Resource, quarter_turns:i32) -> bool {
+		let last_x = self.map_width - 1;
+		let last_z = self.map_depth - 1;
+		let (last_x_f, last_z_f) = (last_x as f32, last_z as f32);
+		let quarter_turns = quarter_turns.rem_euclid(4);
+		if quarter_turns % 2 == 1 && self.map_width != self.map_depth {
+			let (chunk_size, num_chunks_x, num_chunks_z) = (self.chunk_size, self.num_chunks_x, self.num_chunks_z);
+			if quarter_turns == 1 {
+				self.relayout_cells(owner, chunk_size, num_chunks_z, num_chunks_x,
+					|x, z| Some((z, last_z - x)),
+					|p| Vector2::new(last_z_f - p.y, p.x));
+			} else {
+				self.relayout_cells(owner, chunk_size, num_chunks_z, num_chunks_x,
+					|x, z| Some((last_x - z, x)),
+					|p| Vector2::new(p.y, last_x_f - p.x));
+			}
+			self.generate_all_meshes(owner);
+			return true;
+		}
+		match quarter_turns {
+			1 => self.remap_cells(owner, |x, z| (z, last_z - x), |p| Vector2::new(last_z_f - p.y, p.x)),
+			2 => self.remap_cells(owner, |x, z| (last_x - x, last_z - z), |p| Vector2::new(last_x_f - p.x, last_z_f - p.y)),
+			3 => self.remap_cells(owner, |x, z| (last_x - z, x), |p| Vector2::new(p.y, last_x_f - p.x)),
+			_ => false
+		}
+	}
+
+	#[export]
+	pub fn flip_map (&mut self, _owner:&Resource, horizontal:bool) -> bool {
+		if !self.flip_cells(_owner, horizontal) {
+			return false;
+		}
+		return self.push_undo(Action::Flip(horizontal));
+	}
+
+	fn flip_cells (&mut self, owner:&Resource, horizontal:bool) -> bool {
+		let last_x = self.map_width - 1;
+		let last_z = self.map_depth - 1;
+		let (last_x_f, last_z_f) = (last_x as f32, last_z as f32);
+		if horizontal {
+			return self.remap_cells(owner, |x, z| (last_x - x, z), |p| Vector2::new(last_x_f - p.x, p.y));
+		}
+		return self.remap_cells(owner, |x, z| (x, last_z - z), |p| Vector2::new(p.x, last_z_f - p.y));
+	}
+
+	// How strongly an edit applies at a cell. Everything is selected when there is no selection,
+	// while an empty selection leaves every cell alone
+	fn selection_weight (&self, index:usize) -> f32 {
+		match &self.selection {
+			Some(selection) => selection.weight(index),
+			None => 1.0
+		}
+	}
+
+	fn combine_selection (&mut self, mut mask:SelectionMask, op:i32, feather:f32) {
+		mask.feather(feather);
+		let op = SelectionOp::from_i32(op);
+		let mut selection = match self.selection.take() {
+			Some(selection) => selection,
+			// Combining with no selection starts from an empty one, so Subtract and Intersect select nothing
+			None => SelectionMask::new(self.map_width, self.map_depth)
 		};
-		let offset_z:i32 = match z_move {
-			0 => 0,
-			1 => half_size,
-			2 => diff,
-			_ => 0
+		selection.combine(&mask, op);
+		// Kept even when empty, as dropping it would select the whole map
+		self.selection = Some(selection);
+	}
+
+	// Returns false and keeps the current selection when the mask doesn't have one weight per cell
+	#[export]
+	pub fn set_selection_mask (&mut self, _owner:&Resource, mask:TypedArray<f32>) -> bool {
+		if mask.len() as usize != self.map_width * self.map_depth {
+			return false;
+		}
+		self.selection = Some(SelectionMask::from_fn(self.map_width, self.map_depth, |x, z| mask.get((z * self.map_width + x) as i32)));
+		return true;
+	}
+
+	#[export]
+	pub fn select_rect (&mut self, _owner:&Resource, rect:Rect2, op:i32, feather:f32) {
+		let min = Vector2::new(rect.origin.x, rect.origin.y);
+		let max = min + Vector2::new(rect.size.width, rect.size.height);
+		let mask = SelectionMask::rect(self.map_width, self.map_depth, min, max);
+		self.combine_selection(mask, op, feather);
+	}
+
+	#[export]
+	pub fn select_ellipse (&mut self, _owner:&Resource, rect:Rect2, op:i32, feather:f32) {
+		let radius = Vector2::new(rect.size.width, rect.size.height) * 0.5;
+		let center = Vector2::new(rect.origin.x, rect.origin.y) + radius;
+		let mask = SelectionMask::ellipse(self.map_width, self.map_depth, center, radius);
+		self.combine_selection(mask, op, feather);
+	}
+
+	#[export]
+	pub fn select_lasso (&mut self, _owner:&Resource, points:TypedArray<Vector2>, op:i32, feather:f32) {
+		let points = (0..points.len()).map(|i| points.get(i)).collect::<Vec<Vector2>>();
+		let mask = SelectionMask::polygon(self.map_width, self.map_depth, &points);
+		self.combine_selection(mask, op, feather);
+	}
+
+	#[export]
+	pub fn select_height_range (&mut self, _owner:&Resource, min_height:i32, max_height:i32, op:i32, feather:f32) {
+		let mask = SelectionMask::from_fn(self.map_width, self.map_depth, |x, z| {
+			let height = self.heights[z * self.map_width + x];
+			return if height >= min_height && height <= max_height { 1.0 } else { 0.0 };
+		});
+		self.combine_selection(mask, op, feather);
+	}
+
+	#[export]
+	pub fn select_color (&mut self, _owner:&Resource, color:Color, similarity:f32, op:i32, feather:f32) {
+		let color8 = Color8::from_color(color);
+		let matching:Vec<bool> = self.color_list.iter().map(|color| color.similarity(color8) >= similarity).collect::<Vec<bool>>();
+		let mask = SelectionMask::from_fn(self.map_width, self.map_depth, |x, z| {
+			return if matching[self.vertex_colors[z * self.map_width + x]] { 1.0 } else { 0.0 };
+		});
+		self.combine_selection(mask, op, feather);
+	}
+
+	#[export]
+	pub fn select_all (&mut self, _owner:&Resource) {
+		self.selection = None;
+	}
+
+	#[export]
+	pub fn invert_selection (&mut self, _owner:&Resource) {
+		if let Some(selection) = self.selection.as_mut() {
+			selection.invert();
+		}
+	}
+
+	#[export]
+	pub fn clear_selection (&mut self, _owner:&Resource) {
+		self.selection = None;
+	}
+
+	#[export]
+	pub fn has_selection (&self, _owner:&Resource) -> bool {
+		return self.selection.is_some();
+	}
+
+	// Selection weight per cell for drawing an overlay. Empty when nothing is selected
+	#[export]
+	pub fn get_selection_mask (&self, _owner:&Resource) -> TypedArray<f32> {
+		match &self.selection {
+			Some(selection) => TypedArray::from_vec(selection.weights.clone()),
+			None => TypedArray::new()
+		}
+	}
+
+	#[export]
+	pub fn draw_ramp (&mut self, _owner:&Resource, start:Vector2, end:Vector2, width:f32, start_height:i32, end_height:i32, profile:i32, falloff:f32) -> bool {
+		let half_width = (width * 0.5).max(0.5);
+		let falloff = falloff.max(0.0);
+		let reach = half_width + falloff;
+
+		let dir = end - start;
+		let length_squared = dir.square_length();
+
+		let min_x = ((start.x.min(end.x) - reach).floor() as i32).max(0);
+		let max_x = ((start.x.max(end.x) + reach).ceil() as i32).min(self.map_width as i32 - 1);
+		let min_z = ((start.y.min(end.y) - reach).floor() as i32).max(0);
+		let max_z = ((start.y.max(end.y) + reach).ceil() as i32).min(self.map_depth as i32 - 1);
+
+		let mut new_heights:HashMap<usize,i32> = HashMap::new();
+
+		for z in min_z..=max_z {
+			for x in min_x..=max_x {
+				let point = Vector2::new(x as f32, z as f32);
+
+				// Position along the ramp. Cells past either end are left alone so the ramp has flat ends
+				let t:f32 = if length_squared > 0.0 { (point - start).dot(dir) / length_squared } else { 0.0 };
+				if t < 0.0 || t > 1.0 { continue; }
+
+				let dist = point.distance_to(start + dir * t);
+				if dist > reach { continue; }
+
+				let index = z as usize * self.map_width + x as usize;
+				let weight:f32 = if dist <= half_width { 1.0 } else { 1.0 - (dist - half_width) / falloff };
+				let weight = weight * self.selection_weight(index);
+
+				let target_height = start_height as f32 + (end_height - start_height) as f32 * ramp_profile(t, profile);
+				let current_height = self.height(x, z);
+				let new_height = (current_height as f32 + (target_height.round() - current_height as f32) * weight).round() as i32;
+
+				if new_height != current_height {
+					new_heights.insert(index, new_height);
+				}
+			}
+		}
+
+		return self.apply_height_edits(_owner, &new_heights);
+	}
+
+	#[export]
+	pub fn add_spline (&mut self, _owner:&Resource, points:TypedArray<Vector3>, widths:TypedArray<f32>, smooth:bool) -> i64 {
+		let mut spline = Spline::new();
+		spline.set_points(&points, &widths);
+		spline.smooth = smooth;
+		self.splines.push(spline);
+		return self.splines.len() as i64 - 1;
+	}
+
+	#[export]
+	pub fn set_spline_points (&mut self, _owner:&Resource, spline_id:i64, points:TypedArray<Vector3>, widths:TypedArray<f32>, smooth:bool) {
+		if let Some(spline) = self.splines.get_mut(spline_id as usize) {
+			spline.set_points(&points, &widths);
+			spline.smooth = smooth;
+		}
+	}
+
+	#[export]
+	pub fn set_spline_road (&mut self, _owner:&Resource, spline_id:i64, carve_mode:i32, embankment:f32, paint:bool, color:Color) {
+		if let Some(spline) = self.splines.get_mut(spline_id as usize) {
+			spline.carve_mode = carve_mode;
+			spline.embankment = embankment.max(0.0);
+			spline.paint_color = if paint { Some(Color8::from_color(color)) } else { None };
+		}
+	}
+
+	#[export]
+	pub fn remove_spline (&mut self, _owner:&Resource, spline_id:i64) {
+		if (spline_id as usize) < self.splines.len() {
+			self.splines.remove(spline_id as usize);
+		}
+	}
+
+	#[export]
+	pub fn get_spline_count (&self, _owner:&Resource) -> i64 {
+		return self.splines.len() as i64;
+	}
+
+	#[export]
+	pub fn get_spline (&self, _owner:&Resource, spline_id:i64) -> Dictionary {
+		if let Some(spline) = self.splines.get(spline_id as usize) {
+			return spline.to_dictionary();
+		}
+		return Dictionary::new_shared();
+	}
+
+	#[export]
+	pub fn get_splines (&self, _owner:&Resource) -> VariantArray {
+		let arr = VariantArray::new();
+		for spline in self.splines.iter() {
+			arr.push(spline.to_dictionary());
+		}
+		return arr.into_shared();
+	}
+
+	#[export]
+	pub fn set_splines (&mut self, _owner:&Resource, splines:VariantArray) {
+		self.splines.clear();
+		for item in splines.iter() {
+			self.splines.push(Spline::from_dictionary(&item.to_dictionary()));
+		}
+	}
+
+	// Points along the curve as (x, height, z), for drawing the spline in the editor
+	#[export]
+	pub fn get_spline_curve (&self, _owner:&Resource, spline_id:i64, spacing:f32) -> TypedArray<Vector3> {
+		let mut arr:TypedArray<Vector3> = TypedArray::new();
+		if let Some(spline) = self.splines.get(spline_id as usize) {
+			for sample in spline.sample(spacing).iter() {
+				arr.push(Vector3::new(sample.position.x, sample.height, sample.position.y));
+			}
+		}
+		return arr;
+	}
+
+	#[export]
+	pub fn carve_spline (&mut self, _owner:&Resource, spline_id:i64) -> bool {
+		let spline = match self.splines.get(spline_id as usize) {
+			Some(spline) => spline.clone(),
+			None => return false
 		};
+		let samples = spline.sample(0.5);
+		if samples.len() == 0 {
+			return false;
+		}
 
-		let mut new_heights:Vec<i32> = vec![0; (new_map_size * new_map_size) as usize];
-		let mut new_colors:Vec<usize> =  vec![0; (new_map_size * new_map_size) as usize];
-		
+		let reach = spline.max_width() * 0.5 + spline.embankment;
+		let mut min_position = samples[0].position;
+		let mut max_position = samples[0].position;
+		for sample in samples.iter() {
+			min_position = Vector2::new(min_position.x.min(sample.position.x), min_position.y.min(sample.position.y));
+			max_position = Vector2::new(max_position.x.max(sample.position.x), max_position.y.max(sample.position.y));
+		}
 
-		if diff > 0 {
-			for z in 0..self.map_size {
-				for x in 0..self.map_size {
-					let idx = z * self.map_size + x;
-					let current_height = self.heights[idx];
-					let current_color = self.vertex_colors[idx];
-					let new_idx = (z as i32 + offset_z) * new_map_size as i32 + x as i32 + offset_x as i32;
-					new_heights[new_idx as usize] = current_height;
-					new_colors[new_idx as usize] = current_color;
+		let min_x = ((min_position.x - reach).floor() as i32).max(0);
+		let max_x = ((max_position.x + reach).ceil() as i32).min(self.map_width as i32 - 1);
+		let min_z = ((min_position.y - reach).floor() as i32).max(0);
+		let max_z = ((max_position.y + reach).ceil() as i32).min(self.map_depth as i32 - 1);
+
+		let mut new_heights:HashMap<usize,i32> = HashMap::new();
+		let mut new_colors:HashMap<usize,Color8> = HashMap::new();
+
+		for z in min_z..=max_z {
+			for x in min_x..=max_x {
+				let nearest = Spline::nearest(&samples, Vector2::new(x as f32, z as f32));
+				let (dist, point) = match nearest {
+					Some(nearest) => nearest,
+					None => continue
+				};
+
+				let half_width = (point.width * 0.5).max(0.5);
+				let weight:f32 = if dist <= half_width {
+					1.0
+				}
+				else if dist <= half_width + spline.embankment {
+					1.0 - (dist - half_width) / spline.embankment
+				}
+				else {
+					continue;
+				};
+
+				let index = z as usize * self.map_width + x as usize;
+				let weight = weight * self.selection_weight(index);
+				let current_height = self.height(x, z);
+				let mut new_height = (current_height as f32 + (point.height.round() - current_height as f32) * weight).round() as i32;
+				new_height = match spline.carve_mode {
+					1 => new_height.min(current_height),
+					2 => new_height.max(current_height),
+					_ => new_height
+				};
+				if new_height != current_height {
+					new_heights.insert(index, new_height);
+				}
+
+				// Only the road bed is painted, not the embankment
+				if let Some(paint_color) = spline.paint_color {
+					if dist <= half_width && weight > 0.0 {
+						let current_color = self.color_list[self.vertex_colors[index]];
+						new_colors.insert(index, current_color.mix(paint_color, weight));
+					}
 				}
 			}
 		}
-		else {
-			for z in 0..new_map_size {
-				for x in 0..new_map_size {
-					let idx = (z as i32 - offset_z) * self.map_size as i32 + (x as i32 - offset_x);
-					let current_height = self.heights[idx as usize];
-					let current_color = self.vertex_colors[idx as usize];
-					let new_idx = z * new_map_size + x;
-					new_heights[new_idx as usize] = current_height;
-					new_colors[new_idx as usize] = current_color;
+
+		return self.apply_terrain_edits(_owner, &new_heights, &new_colors);
+	}
+
+	// Remaps heights into flat bands. Explicit band edges take priority over step_count,
+	// which otherwise splits the range between the lowest and highest selected cell evenly
+	#[export]
+	pub fn terrace (&mut self, _owner:&Resource, step_count:i32, band_edges:TypedArray<i32>, sharpness:f32) -> bool {
+		let mut edges:Vec<i32> = (0..band_edges.len()).map(|i| band_edges.get(i)).collect::<Vec<i32>>();
+		edges.sort();
+		edges.dedup();
+
+		if edges.len() < 2 {
+			let mut min_height:Option<i32> = None;
+			let mut max_height:Option<i32> = None;
+			for i in 0..self.heights.len() {
+				if self.selection_weight(i) <= 0.0 { continue; }
+				min_height = Some(min_height.map_or(self.heights[i], |h| h.min(self.heights[i])));
+				max_height = Some(max_height.map_or(self.heights[i], |h| h.max(self.heights[i])));
+			}
+			let (min_height, max_height) = match (min_height, max_height) {
+				(Some(min_height), Some(max_height)) => (min_height, max_height),
+				_ => return false
+			};
+
+			let step_count = step_count.max(1);
+			edges = (0..=step_count).map(|i| {
+				return min_height + ((max_height - min_height) as f32 * i as f32 / step_count as f32).round() as i32;
+			}).collect::<Vec<i32>>();
+			edges.dedup();
+			if edges.len() < 2 {
+				return false;
+			}
+		}
+
+		let mut new_heights:HashMap<usize,i32> = HashMap::new();
+		for i in 0..self.heights.len() {
+			let weight = self.selection_weight(i);
+			if weight <= 0.0 { continue; }
+
+			let current_height = self.heights[i];
+			let terraced_height = terrace_height(current_height, &edges, sharpness);
+			let new_height = (current_height as f32 + (terraced_height - current_height) as f32 * weight).round() as i32;
+			if new_height != current_height {
+				new_heights.insert(i, new_height);
+			}
+		}
+
+		return self.apply_global_height_edits(_owner, &new_heights);
+	}
+
+	// Copies heights and colors inside the rectangle, optionally limited to the selection
+	#[export]
+	pub fn copy_region (&mut self, _owner:&Resource, rect:Rect2, use_selection:bool) -> bool {
+		let min_x = (rect.origin.x.floor() as i32).max(0);
+		let min_z = (rect.origin.y.floor() as i32).max(0);
+		let max_x = ((rect.origin.x + rect.size.width).ceil() as i32).min(self.map_width as i32);
+		let max_z = ((rect.origin.y + rect.size.height).ceil() as i32).min(self.map_depth as i32);
+		if max_x <= min_x || max_z <= min_z {
+			return false;
+		}
+
+		let mut region = TerrainRegion::new((max_x - min_x) as usize, (max_z - min_z) as usize);
+		for z in 0..region.size_z {
+			for x in 0..region.size_x {
+				let index = (z + min_z as usize) * self.map_width + x + min_x as usize;
+				let region_index = z * region.size_x + x;
+				region.heights[region_index] = self.heights[index];
+				region.colors[region_index] = self.color_list[self.vertex_colors[index]];
+				region.weights[region_index] = if use_selection { self.selection_weight(index) } else { 1.0 };
+			}
+		}
+
+		self.clipboard = Some(region);
+		return true;
+	}
+
+	#[export]
+	pub fn has_clipboard (&self, _owner:&Resource) -> bool {
+		return self.clipboard.is_some();
+	}
+
+	#[export]
+	pub fn get_clipboard_size (&self, _owner:&Resource, rotation:i32) -> Vector2 {
+		match &self.clipboard {
+			Some(region) if rotation.rem_euclid(2) == 1 => Vector2::new(region.size_z as f32, region.size_x as f32),
+			Some(region) => Vector2::new(region.size_x as f32, region.size_z as f32),
+			None => Vector2::new(0.0, 0.0)
+		}
+	}
+
+	// Pastes the clipboard with its top left corner at `position`.
+	// paste_mode: 0 replace, 1 add, 2 max, 3 min. When relative_to_destination is set, the lowest copied
+	// cell is placed at the destination's height under the centre of the paste, plus height_offset
+	#[export]
+	pub fn paste_region (&mut self, _owner:&Resource, position:Vector2, rotation:i32, mirror_x:bool, mirror_z:bool, paste_mode:i32, height_offset:i32, relative_to_destination:bool, paste_colors:bool) -> bool {
+		let region = match &self.clipboard {
+			Some(region) => region.transformed(rotation, mirror_x, mirror_z),
+			None => return false
+		};
+
+		let origin_x = position.x.floor() as i32;
+		let origin_z = position.y.floor() as i32;
+		let region_min_height = region.min_height();
+
+		let mut base_height = height_offset;
+		if relative_to_destination {
+			let center_x = (origin_x + region.size_x as i32 / 2).max(0).min(self.map_width as i32 - 1);
+			let center_z = (origin_z + region.size_z as i32 / 2).max(0).min(self.map_depth as i32 - 1);
+			base_height += self.height(center_x, center_z) - region_min_height;
+		}
+
+		let mut new_heights:HashMap<usize,i32> = HashMap::new();
+		let mut new_colors:HashMap<usize,Color8> = HashMap::new();
+
+		for z in 0..region.size_z {
+			let global_z = origin_z + z as i32;
+			if global_z < 0 || global_z >= self.map_depth as i32 { continue; }
+			for x in 0..region.size_x {
+				let global_x = origin_x + x as i32;
+				if global_x < 0 || global_x >= self.map_width as i32 { continue; }
+
+				let region_index = z * region.size_x + x;
+				let index = global_z as usize * self.map_width + global_x as usize;
+				let weight = region.weights[region_index] * self.selection_weight(index);
+				if weight <= 0.0 { continue; }
+
+				let current_height = self.heights[index];
+				let pasted_height = region.heights[region_index] + base_height;
+				let target_height = match paste_mode {
+					1 => current_height + region.heights[region_index] - region_min_height + height_offset,
+					2 => current_height.max(pasted_height),
+					3 => current_height.min(pasted_height),
+					_ => pasted_height
+				};
+				let new_height = current_height + ((target_height - current_height) as f32 * weight).round() as i32;
+				if new_height != current_height {
+					new_heights.insert(index, new_height);
+				}
+
+				if paste_colors {
+					let current_color = self.color_list[self.vertex_colors[index]];
+					new_colors.insert(index, current_color.mix(region.colors[region_index], weight));
 				}
 			}
 		}
 
+		return self.apply_terrain_edits(_owner, &new_heights, &new_colors);
+	}
+
+	// x_move and z_move anchor the old map when the size changes: 0 at the start, 1 centred, 2 at the end
+	#[export]
+	fn resize_terrain (&mut self, _owner:&Resource, new_chunk_size:i32, new_num_chunks_x:i32, new_num_chunks_z:i32, x_move:i32, z_move:i32) {
+		let new_chunk_size = new_chunk_size.max(1);
+		let new_num_chunks_x = new_num_chunks_x.max(1);
+		let new_num_chunks_z = new_num_chunks_z.max(1);
+		let diff_x:i32 = new_chunk_size * new_num_chunks_x - self.map_width as i32;
+		let diff_z:i32 = new_chunk_size * new_num_chunks_z - self.map_depth as i32;
+
+		let anchor_offset = |diff:i32, anchor:i32| -> i32 {
+			match anchor {
+				1 => ((diff as f32) / 2.0).trunc() as i32,
+				2 => diff,
+				_ => 0
+			}
+		};
+
+		self.resize_cells(_owner, new_chunk_size as usize, new_num_chunks_x as usize, new_num_chunks_z as usize, anchor_offset(diff_x, x_move), anchor_offset(diff_z, z_move));
+	}
+
+	// Adds chunks to each side of the map. Negative amounts crop chunks away instead
+	#[export]
+	fn pad_terrain (&mut self, _owner:&Resource, left:i32, right:i32, top:i32, bottom:i32) -> bool {
+		let new_num_chunks_x = self.num_chunks_x as i32 + left + right;
+		let new_num_chunks_z = self.num_chunks_z as i32 + top + bottom;
+		if new_num_chunks_x < 1 || new_num_chunks_z < 1 {
+			return false;
+		}
+		let chunk_size = self.chunk_size as i32;
+		self.resize_cells(_owner, self.chunk_size, new_num_chunks_x as usize, new_num_chunks_z as usize, left * chunk_size, top * chunk_size);
+		return true;
+	}
+
+	// Rebuilds the map with a new chunk grid. Old cell (x, z) moves to (x + offset_x, z + offset_z),
+	// cells pushed outside are dropped and new cells start flat with no color
+	fn resize_cells (&mut self, _owner:&Resource, new_chunk_size:usize, new_num_chunks_x:usize, new_num_chunks_z:usize, offset_x:i32, offset_z:i32) {
+		let new_map_width = new_chunk_size * new_num_chunks_x;
+		let new_map_depth = new_chunk_size * new_num_chunks_z;
+		if new_map_width == self.map_width && new_map_depth == self.map_depth && new_chunk_size == self.chunk_size && offset_x == 0 && offset_z == 0 {
+			return;
+		}
+
+		let (map_width, map_depth) = (self.map_width as i32, self.map_depth as i32);
+		let offset = Vector2::new(offset_x as f32, offset_z as f32);
+		self.relayout_cells(_owner, new_chunk_size, new_num_chunks_x, new_num_chunks_z,
+			|x, z| {
+				let (old_x, old_z) = (x as i32 - offset_x, z as i32 - offset_z);
+				if old_x < 0 || old_x >= map_width || old_z < 0 || old_z >= map_depth {
+					return None;
+				}
+				return Some((old_x as usize, old_z as usize));
+			},
+			|position| position + offset
+		);
+		// Resizes can drop cells so they aren't undoable, and older undo data is indexed by the old layout
+		self.undo_stack.clear();
+		self.redo_stack.clear();
+	}
+
+	// Rebuilds the map with a new chunk grid, filling new cell (x, z) from the old cell given by source_of.
+	// Cells without a source start flat with no color. Splines are stored in map coordinates, so
+	// move_point carries them over to the new layout. The undo history is left to the caller
+	fn relayout_cells<F, P> (&mut self, _owner:&Resource, new_chunk_size:usize, new_num_chunks_x:usize, new_num_chunks_z:usize, source_of:F, move_point:P)
+	where F:Fn(usize, usize) -> Option<(usize, usize)>, P:Fn(Vector2) -> Vector2 {
+		let new_map_width = new_chunk_size * new_num_chunks_x;
+		let new_map_depth = new_chunk_size * new_num_chunks_z;
+
+		let mut new_heights:Vec<i32> = vec![self.clamp_height(0); new_map_width * new_map_depth];
+		let mut new_colors:Vec<usize> = vec![0; new_map_width * new_map_depth];
+		let mut new_splats:Vec<SplatWeights> = if self.splat_channels > 0 { vec![full_weight(0); new_map_width * new_map_depth] } else { Vec::new() };
+
+		for z in 0..new_map_depth {
+			for x in 0..new_map_width {
+				let (old_x, old_z) = match source_of(x, z) {
+					Some(source) => source,
+					None => continue
+				};
+				let idx = old_z * self.map_width + old_x;
+				new_heights[z * new_map_width + x] = self.heights[idx];
+				new_colors[z * new_map_width + x] = self.vertex_colors[idx];
+				if self.splat_channels > 0 {
+					new_splats[z * new_map_width + x] = self.splat_weights[idx];
+				}
+			}
+		}
 
 		self.heights = new_heights;
 		self.vertex_colors = new_colors;
+		self.splat_weights = new_splats;
 
-		self.chunk_size = new_chunk_size as usize;
-		self.num_chunks = new_num_chunks as usize;
-		self.map_size = new_map_size as usize;
+		self.chunk_size = new_chunk_size;
+		self.num_chunks_x = new_num_chunks_x;
+		self.num_chunks_z = new_num_chunks_z;
+		self.map_width = new_map_width;
+		self.map_depth = new_map_depth;
 
+		for spline in self.splines.iter_mut() {
+			for point in spline.points.iter_mut() {
+				point.position = move_point(point.position);
+			}
+		}
 
+		self.reset_chunk_versions();
 		self.chunk_vertices.clear();
 		self.chunk_indices.clear();
-		self.chunk_lod = vec![0; self.num_chunks * self.num_chunks];
-		for _chunk_id in 0..self.num_chunks * self.num_chunks {
+		self.chunk_lod = vec![0; self.chunk_count()];
+		self.lod_position = None;
+		for _chunk_id in 0..self.chunk_count() {
 			self.chunk_vertices.push(Vec::new());
 			self.chunk_indices.push(Vec::new());
 		}
@@ -907,7 +2501,10 @@ impl TerrainUtils {
 		self.update_all_chunks(_owner);
 		self.update_terrain_arrays();
 		self.edited_positions.clear();
+		self.edited_colors.clear();
+		self.edited_splats.clear();
 		self.chunks_at_seam.clear();
+		self.selection = None;
 	}
 
 
@@ -927,8 +2524,8 @@ impl TerrainUtils {
 
 		for c in 0..chunk_ids.len() {
 			let chunk_id = chunk_ids.get(c) as usize;
-			let offset_x = self.chunk_size as i32 * (chunk_id as i32 % self.num_chunks as i32);
-			let offset_z = self.chunk_size as i32 * (chunk_id as i32 / self.num_chunks as i32);
+			let offset_x = self.chunk_size as i32 * (chunk_id as i32 % self.num_chunks_x as i32);
+			let offset_z = self.chunk_size as i32 * (chunk_id as i32 / self.num_chunks_x as i32);
 			
 			for i in (0..self.chunk_indices[chunk_id].len()).step_by(3) {
 				let mut face_verts:[Vector3; 3] = [Vector3::new(0.0, 0.0, 0.0); 3];
@@ -961,8 +2558,8 @@ impl TerrainUtils {
 		self.terrain_indices.clear();
 
 		for chunk_id in 0..self.chunk_indices.len() {
-			let offset_x = self.chunk_size as i32 * (chunk_id as i32 % self.num_chunks as i32);
-			let offset_z = self.chunk_size as i32 * (chunk_id as i32 / self.num_chunks as i32);
+			let offset_x = self.chunk_size as i32 * (chunk_id as i32 % self.num_chunks_x as i32);
+			let offset_z = self.chunk_size as i32 * (chunk_id as i32 / self.num_chunks_x as i32);
 			
 			for i in (0..self.chunk_indices[chunk_id].len()).step_by(3) {
 				let mut face_verts:[Vector3; 3] = [Vector3::new(0.0, 0.0, 0.0); 3];
@@ -988,91 +2585,256 @@ impl TerrainUtils {
 	}
 
 
-	//indices_out:&mut Vec<usize>, vertices_out:&mut Vec<Vector3>, normals_out:&mut Vec<Vector3>, 
+	// A view of the terrain for meshing chunks, on this thread or the worker pool
+	fn mesher (&self) -> ChunkMesher<'_> {
+		ChunkMesher {
+			heights: Cow::Borrowed(&self.heights),
+			vertex_colors: Cow::Borrowed(&self.vertex_colors),
+			color_list: Cow::Borrowed(&self.color_list),
+			splat_channels: self.splat_channels,
+			splat_weights: Cow::Borrowed(&self.splat_weights),
+			chunk_lod: Cow::Borrowed(&self.chunk_lod),
+			map_width: self.map_width,
+			map_depth: self.map_depth,
+			chunk_size: self.chunk_size,
+			num_chunks_x: self.num_chunks_x,
+			num_chunks_z: self.num_chunks_z,
+			simplify_flat: self.simplify_flat,
+			border_walls: self.border_walls,
+			border_cap: self.border_cap,
+			base_height: self.base_height.min(self.min_height) as f32,
+			crease_angle: self.shading_crease_angle(),
+			generate_uvs: self.generate_uvs,
+			uv_scale: self.uv_scale,
+		}
+	}
+
 	#[export]
 	fn update_chunk (&mut self, _owner:&Resource, chunk_id:usize) {
-		self.chunk_vertices[chunk_id].clear();
-		self.chunk_indices[chunk_id].clear();
+		let (vertices, indices) = self.mesher().build_chunk(chunk_id);
+		self.chunk_vertices[chunk_id] = vertices;
+		self.chunk_indices[chunk_id] = indices;
+		self.mark_chunk_current(chunk_id);
+	}
 
-		if self.chunk_lod[chunk_id] > 0 {
-			generate_chunk_lod_mesh(&self.heights, chunk_id, self.chunk_size, self.num_chunks, &self.chunk_lod, &mut self.chunk_indices[chunk_id], &mut self.chunk_vertices[chunk_id]);
+	#[export]
+	fn update_all_chunks (&mut self, _owner:&Resource) {
+		let chunk_ids:Vec<usize> = (0..self.chunk_indices.len()).collect();
+		let results:Vec<(Vec<Vector3>, Vec<usize>)> = {
+			let mesher = self.mesher();
+			chunk_ids.par_iter().map(|chunk_id| mesher.build_chunk(*chunk_id)).collect()
+		};
+		for (chunk_id, (vertices, indices)) in results.into_iter().enumerate() {
+			self.chunk_vertices[chunk_id] = vertices;
+			self.chunk_indices[chunk_id] = indices;
+			self.mark_chunk_current(chunk_id);
 		}
+	}
 
-		else {
-			let vertices:Vec<Vector3> = generate_chunk_mesh_from_heightmap(&self.heights, chunk_id, self.chunk_size, self.num_chunks, &self.chunk_lod);
-			let mut vertex_map:HashMap<Vector3Key,usize> = HashMap::new();
-
-			for i in (0..vertices.len()).step_by(3) {
-				let points = [
-					vertices[i],
-					vertices[i + 1],
-					vertices[i + 2],
-				];
-				for point in points.iter() {
-					let point = *point;
-					let vert_key = Vector3Key::from_vector3(point);
-					let vert_index = vertex_map.get(&vert_key);
-					if let Some(vert_index) = vert_index {
-						self.chunk_indices[chunk_id].push(*vert_index);
-					}
-					else {
-						let new_index = self.chunk_vertices[chunk_id].len();
-						vertex_map.insert(vert_key, new_index);
-						self.chunk_vertices[chunk_id].push(point);
-						self.chunk_indices[chunk_id].push(new_index);
-					}
-				}
+	#[export]
+    fn generate_chunk_mesh (&mut self, _owner:&Resource, chunk_id:usize) {
+		if chunk_id < self.chunks.len() {
+			let surface = self.mesher().build_surface(chunk_id, &self.chunk_vertices[chunk_id], &self.chunk_indices[chunk_id]);
+			self.commit_surface(chunk_id, surface);
+		}
+    }
+
+	#[export]
+	fn generate_all_meshes (&mut self, _owner:&Resource) {
+		let chunk_ids:Vec<usize> = (0..self.chunks.len().min(self.chunk_vertices.len())).collect();
+		let surfaces:Vec<ChunkSurface> = {
+			let mesher = self.mesher();
+			let chunk_vertices = &self.chunk_vertices;
+			let chunk_indices = &self.chunk_indices;
+			chunk_ids.par_iter().map(|chunk_id| mesher.build_surface(*chunk_id, &chunk_vertices[*chunk_id], &chunk_indices[*chunk_id])).collect()
+		};
+		for (chunk_id, surface) in surfaces.into_iter().enumerate() {
+			self.commit_surface(chunk_id, surface);
+		}
+	}
+
+	// Updates and shades the chunks on the worker pool, then commits their meshes here. With asynchronous remeshing
+	// the chunks are only queued, and poll_rebuild puts them in place once they are built
+	fn rebuild_chunks (&mut self, chunk_ids:&Vec<usize>) {
+		if self.async_remesh {
+			self.queue_chunks(chunk_ids);
+			return;
+		}
+		let results = self.mesher().build_all(chunk_ids);
+		for result in results {
+			self.mark_chunk_current(result.chunk_id);
+			self.apply_chunk_result(result);
+		}
+	}
+
+	fn apply_chunk_result (&mut self, result:ChunkResult) {
+		if result.chunk_id >= self.chunk_vertices.len() {
+			return;
+		}
+		self.chunk_vertices[result.chunk_id] = result.vertices;
+		self.chunk_indices[result.chunk_id] = result.indices;
+		if result.chunk_id < self.chunks.len() {
+			self.commit_surface(result.chunk_id, result.surface);
+		}
+	}
+
+	// Godot objects can only be touched from the main thread, so this is the one step that never runs on the pool
+	fn commit_surface (&self, chunk_id:usize, surface:ChunkSurface) {
+		let st = SurfaceTool::new();
+		st.begin(4);
+
+		for i in 0..surface.vertices.len() {
+			st.add_color(surface.colors[i]);
+			st.add_normal(surface.normals[i]);
+			if self.generate_uvs && surface.uvs.len() == surface.vertices.len() {
+				st.add_uv(surface.uvs[i]);
+				st.add_uv2(surface.uv2s[i]);
 			}
+			st.add_vertex(surface.vertices[i]);
+		}
 
+		for index in surface.indices.iter() {
+			st.add_index(*index as i64);
+		}
+
+		let mesh = st.commit(GodotObject::null(), self.mesh_compress_flags());
+		let mesh_instance = unsafe { self.chunks[chunk_id].assume_safe() };
+		if let Some(mesh) = mesh {
+			mesh_instance.set_mesh(mesh);
 		}
 	}
 
+	// Strokes and other edits only queue the chunks they change instead of remeshing them before returning.
+	// Call poll_rebuild every frame to build the queue in the background and show the results
 	#[export]
-	fn update_all_chunks (&mut self, _owner:&Resource) {
-		for i in 0..self.chunk_indices.len() {
-			self.update_chunk(_owner, i as usize);
+	pub fn set_async_remesh (&mut self, _owner:&Resource, enabled:bool) {
+		self.async_remesh = enabled;
+		if !enabled {
+			// Nothing polls for the chunks still waiting once this is off, so they are built right away
+			let mut chunk_ids:Vec<usize> = self.queued_chunks.iter().cloned().collect();
+			chunk_ids.extend(self.building_chunks.keys().filter(|chunk_id| !self.queued_chunks.contains(*chunk_id)));
+			self.building_chunks.clear();
+			self.rebuild_chunks(&chunk_ids);
 		}
 	}
 
 	#[export]
-    fn generate_chunk_mesh (&mut self, _owner:&Resource, chunk_id:usize) {
-		if chunk_id < self.chunks.len() {
-			let offset_x = self.chunk_size as i32 * (chunk_id as i32 % self.num_chunks as i32);
-			let offset_z = self.chunk_size as i32 * (chunk_id as i32 / self.num_chunks as i32);
-			let st = SurfaceTool::new();
-			st.begin(4);
+	pub fn get_async_remesh (&self, _owner:&Resource) -> bool { self.async_remesh }
 
-			if self.shade_smooth {
-				st.add_smooth_group(true);
+	// Starts rebuilding the given chunks, or every chunk when the array is empty, on the worker pool and returns
+	// straight away. Call poll_rebuild every frame to put the finished chunks in place
+	#[export]
+	pub fn start_rebuild (&mut self, _owner:&Resource, chunk_ids:TypedArray<i32>) {
+		let mut ids:Vec<usize> = Vec::new();
+		for i in 0..chunk_ids.len() {
+			let chunk_id = chunk_ids.get(i);
+			if chunk_id >= 0 && (chunk_id as usize) < self.chunk_count() {
+				ids.push(chunk_id as usize);
 			}
+		}
+		if chunk_ids.len() == 0 {
+			ids = (0..self.chunk_count()).collect();
+		}
+		self.queue_chunks(&ids);
+		self.rebuild_requested = true;
+		self.dispatch_queued_chunks();
+	}
 
-			for i in 0..self.chunk_vertices[chunk_id].len() {
-				let vert = self.chunk_vertices[chunk_id][i];
-				let vert_floor:Vector2 = Vector2::new(vert.x.floor() + offset_x as f32, vert.z.floor() + offset_z as f32);
-				let color = self.get_vertex_color(vert_floor.x as i32, vert_floor.y as i32);
-				st.add_color(color.to_color());
-				st.add_vertex(vert);
+	// Puts up to max_chunks finished chunks in place, or all of them when max_chunks is zero or less, emitting
+	// chunk_mesh_ready for each, then sends the queued chunks to the worker pool. Returns true once nothing is left to
+	// build. The terrain arrays are brought up to date when a rebuild started by start_rebuild completes
+	#[export]
+	pub fn poll_rebuild (&mut self, _owner:&Resource, max_chunks:i64) -> bool {
+		let mut committed:i64 = 0;
+		while max_chunks <= 0 || committed < max_chunks {
+			let result = match self.remesh_results.try_recv() {
+				Ok(result) => result,
+				Err(_) => break
+			};
+			let chunk_id = result.chunk_id;
+			if self.building_chunks.get(&chunk_id) == Some(&result.version) {
+				self.building_chunks.remove(&chunk_id);
 			}
-
-			for index in self.chunk_indices[chunk_id].iter() {
-				st.add_index(*index as i64);
+			// Stale: the chunk already shows something newer, or the map was rebuilt since the snapshot
+			if chunk_id >= self.shown_versions.len() || result.version <= self.shown_versions[chunk_id] {
+				continue;
 			}
+			// A result that is behind the latest edit is still newer than what is on screen. The chunk stays queued
+			self.shown_versions[chunk_id] = result.version;
+			self.apply_chunk_result(result);
+			_owner.emit_signal("chunk_mesh_ready", &[Variant::from_i64(chunk_id as i64)]);
+			committed += 1;
+		}
+		self.dispatch_queued_chunks();
 
-			st.generate_normals(false);
-
-			let mesh = st.commit(GodotObject::null(), 2194432);
-			let mesh_instance = unsafe { self.chunks[chunk_id].assume_safe() };
-			if let Some(mesh) = mesh {
-				mesh_instance.set_mesh(mesh);
-			}
+		let is_done = self.queued_chunks.is_empty() && self.building_chunks.is_empty();
+		if is_done && self.rebuild_requested {
+			self.rebuild_requested = false;
+			self.update_terrain_arrays();
 		}
-    }
+		return is_done;
+	}
 
 	#[export]
-	fn generate_all_meshes (&mut self, _owner:&Resource) {
-		for i in 0..self.chunks.len() {
-			self.generate_chunk_mesh(_owner, i);
+	pub fn get_pending_chunk_count (&self, _owner:&Resource) -> i64 {
+		let building_only = self.building_chunks.keys().filter(|chunk_id| !self.queued_chunks.contains(*chunk_id)).count();
+		return (self.queued_chunks.len() + building_only) as i64;
+	}
+
+	// Marks the chunks' data as changed and queues them for a background rebuild
+	fn queue_chunks (&mut self, chunk_ids:&Vec<usize>) {
+		for chunk_id in chunk_ids.iter() {
+			if *chunk_id >= self.chunk_versions.len() { continue; }
+			self.chunk_versions[*chunk_id] = self.next_version;
+			self.next_version += 1;
+			self.queued_chunks.insert(*chunk_id);
+		}
+	}
+
+	// Records that a chunk was just rebuilt from the current data, so no background result from before can replace it
+	fn mark_chunk_current (&mut self, chunk_id:usize) {
+		if chunk_id >= self.chunk_versions.len() {
+			return;
 		}
+		self.chunk_versions[chunk_id] = self.next_version;
+		self.shown_versions[chunk_id] = self.next_version;
+		self.next_version += 1;
+		self.queued_chunks.remove(&chunk_id);
+	}
+
+	// paint_vertex recolors meshes in place. A build that is still on its way would bring the old colors back,
+	// so it is dropped and the chunk is built again from the painted data
+	fn repaint_chunk (&mut self, chunk_id:usize) {
+		if let Some(version) = self.building_chunks.get(&chunk_id).cloned() {
+			self.shown_versions[chunk_id] = version;
+			self.queue_chunks(&vec![chunk_id]);
+		}
+	}
+
+	// Sends the queued chunks to the worker pool with a snapshot of the terrain. A chunk that is still being built
+	// waits for that build to come back, so a long stroke never piles up work for the same chunk
+	fn dispatch_queued_chunks (&mut self) {
+		let chunks:Vec<(usize,u64)> = self.queued_chunks.iter()
+			.filter(|chunk_id| !self.building_chunks.contains_key(*chunk_id))
+			.map(|chunk_id| (*chunk_id, self.chunk_versions[*chunk_id])).collect();
+		if chunks.len() == 0 {
+			return;
+		}
+		for (chunk_id, version) in chunks.iter() {
+			self.queued_chunks.remove(chunk_id);
+			self.building_chunks.insert(*chunk_id, *version);
+		}
+		spawn_rebuild(self.mesher().into_owned(), chunks, self.remesh_sender.clone());
+	}
+
+	// Chunk ids change meaning when the map is rebuilt. New versions make every result still on its way stale
+	fn reset_chunk_versions (&mut self) {
+		self.chunk_versions = vec![self.next_version; self.chunk_count()];
+		self.shown_versions = vec![self.next_version; self.chunk_count()];
+		self.next_version += 1;
+		self.queued_chunks.clear();
+		self.building_chunks.clear();
+		self.rebuild_requested = false;
 	}
 
 	#[export]
@@ -1098,9 +2860,9 @@ impl TerrainUtils {
 					let current_height:i32 = self.height_by_index(*index);
 					inv_positions.insert(*index, current_height);
 
-					let x = index % self.map_size;
-					let z = index / self.map_size;
-					let chunk_id:usize = (z / self.chunk_size) * self.num_chunks + (x / self.chunk_size);
+					let x = index % self.map_width;
+					let z = index / self.map_width;
+					let chunk_id:usize = self.chunk_id_at(x, z);
 					self.set_height_by_index(*index, *height);
 					self.check_seam_condition(x as i32, z as i32);  
 					chunks_to_update.insert(chunk_id);
@@ -1134,11 +2896,11 @@ impl TerrainUtils {
 					let prev_color = self.color_list[self.vertex_colors[*idx]];
 					inv_colors.insert(*idx,prev_color);
 
-					let global_z = idx / self.map_size;
-					let global_x = idx % self.map_size;
+					let global_z = idx / self.map_width;
+					let global_x = idx % self.map_width;
 					let chunk_row = global_z as usize / self.chunk_size;
 					let chunk_col = global_x as usize / self.chunk_size;
-					let chunk_id = chunk_row * self.num_chunks + chunk_col;
+					let chunk_id = chunk_row * self.num_chunks_x + chunk_col;
 					chunks_to_update.insert(chunk_id);
 					self.set_vertex_color(global_x as i32, global_z as i32, *color);
 					self.check_seam_condition(global_x as i32, global_z as i32);  
@@ -1160,6 +2922,41 @@ impl TerrainUtils {
 				}
 				inv_action = Action::VertexColor(inv_colors);
 			}
+
+			Action::SplatWeights(data) => {
+				let mut chunks_to_update:HashSet<usize> = HashSet::new();
+				self.chunks_at_seam.clear();
+				let mut inv_splats = self.write_splats(&data, &mut chunks_to_update);
+				self.remesh_chunks(owner, &chunks_to_update);
+				// Cells that already held the saved weights stay in the inverse, so nothing is lost while splat mode is off
+				for (index,weights) in data.iter() {
+					inv_splats.entry(*index).or_insert(*weights);
+				}
+				inv_action = Action::SplatWeights(inv_splats);
+			}
+
+			Action::SplatChannels(channels, weights) => {
+				inv_action = self.write_splat_channels(owner, channels, weights);
+			}
+
+			Action::Rotate(quarter_turns) => {
+				self.rotate_cells(owner, quarter_turns);
+				inv_action = Action::Rotate(4 - quarter_turns);
+			}
+
+			Action::Flip(horizontal) => {
+				self.flip_cells(owner, horizontal);
+				inv_action = Action::Flip(horizontal);
+			}
+
+			Action::Batch(actions) => {
+				// Undo in reverse so the inverse batch replays in the original order
+				let mut inv_actions:Vec<Action> = Vec::new();
+				for action in actions.into_iter().rev() {
+					inv_actions.push(self.do_action(owner, action));
+				}
+				inv_action = Action::Batch(inv_actions);
+			}
 		}
 		return inv_action;
 	}
@@ -1184,74 +2981,130 @@ impl TerrainUtils {
 		return self.redo_stack.len() > 0;
 	}
 
-	/*#[export]
+	#[export]
 	fn data_to_file (&self, _owner:&Resource, path:GodotString, settings:Dictionary) {
-		godot_print!("{} {}", self.get_heights(_owner).len(), self.heights.len());
 		let file:Ref<File,Unique> = File::new();
 		file.open(path, File::WRITE).unwrap();
-		let variables= Dictionary::new();
+		let variables = Dictionary::new();
 		variables.insert("heights".to_variant(), self.get_heights(_owner));
-		variables.insert("num_chunks".to_variant(), self.num_chunks as i64);
+		variables.insert("num_chunks_x".to_variant(), self.num_chunks_x as i64);
+		variables.insert("num_chunks_z".to_variant(), self.num_chunks_z as i64);
 		variables.insert("chunk_size".to_variant(), self.chunk_size as i64);
-		variables.insert("step_height".to_variant(), self.step_height);
+		variables.insert("min_height".to_variant(), self.min_height);
+		variables.insert("max_height".to_variant(), self.max_height);
 		variables.insert("vertex_colors".to_variant(), self.get_vertex_colors(_owner));
 		variables.insert("color_list".to_variant(), self.get_color_list(_owner));
+		variables.insert("splat_channels".to_variant(), self.splat_channels as i64);
+		variables.insert("splat_weights".to_variant(), self.get_splat_data(_owner));
+		variables.insert("splines".to_variant(), self.get_splines(_owner));
+		variables.insert("paint_rules".to_variant(), self.get_paint_rules(_owner));
 		variables.insert("settings".to_variant(), settings);
 		file.store_var(variables, true);
 		file.close();
 	}
 
-	
 	#[export]
-	fn init_from_file (&mut self, _owner:&Resource, path:GodotString) {
+	fn init_from_file (&mut self, _owner:&Resource, path:GodotString) -> bool {
 		let file:Ref<File,Unique> = File::new();
-		file.open(path, File::READ).unwrap();
+		if file.open(path, File::READ).is_err() {
+			return false;
+		}
 		let variables = file.get_var(true).to_dictionary();
-		if variables.contains("heights".to_variant()) {
-			self.num_chunks = variables.get("num_chunks").to_i64() as usize;
-			self.chunk_size = variables.get("chunk_size").to_i64() as usize;
-			self.map_size = self.num_chunks * self.chunk_size;
+		file.close();
 
-			self.heights = vec![0; self.map_size * self.map_size];
+		if !variables.contains("heights") {
+			return false;
+		}
 
-			self.step_height = variables.get("step_height").to_f64() as f32;
+		let mut vertex_colors:TypedArray<i32> = TypedArray::new();
+		let mut color_list:TypedArray<Color> = TypedArray::new();
+		if variables.contains("vertex_colors") && variables.contains("color_list") {
+			vertex_colors = variables.get("vertex_colors").to_int32_array();
+			color_list = variables.get("color_list").to_color_array();
+		}
 
-			let _heights = &variables.get("heights").to_int32_array();
-			godot_print!("{} {}", self.heights.len(), _heights.len());
-			for i in 0.._heights.len() {
-				self.heights[i as usize] = _heights.get(i);
-			}
+		// Maps were square before the chunk grid could have a different width and depth
+		let (num_chunks_x, num_chunks_z) = if variables.contains("num_chunks_x") && variables.contains("num_chunks_z") {
+			(variables.get("num_chunks_x").to_i64(), variables.get("num_chunks_z").to_i64())
+		}
+		else {
+			(variables.get("num_chunks").to_i64(), variables.get("num_chunks").to_i64())
+		};
 
-			//godot_print!("Leaves: {} | Total: {}", self.height_quadtree.num_leaf_nodes(), self.height_quadtree.len());
+		self.init_params(
+			_owner,
+			num_chunks_x,
+			num_chunks_z,
+			variables.get("chunk_size").to_i64(),
+			variables.get("heights").to_int32_array(),
+			color_list,
+			vertex_colors
+		);
+
+		// Files saved before the height range was configurable could only hold heights from 0 up
+		self.min_height = DEFAULT_MIN_HEIGHT;
+		self.max_height = DEFAULT_MAX_HEIGHT;
+		if variables.contains("min_height") && variables.contains("max_height") {
+			let min_height = variables.get("min_height").to_i64() as i32;
+			let max_height = variables.get("max_height").to_i64() as i32;
+			self.set_height_range(_owner, min_height, max_height);
+		}
 
-			self.vertex_colors = vec![0; self.map_size * self.map_size];
-			self.color_list = vec![Color8::new(0,0,0,0); 1];
-			self.chunk_lod = vec![0; self.num_chunks * self.num_chunks];
-			
+		self.splines.clear();
+		if variables.contains("splines") {
+			self.set_splines(_owner, variables.get("splines").to_array());
+		}
 
-			self.chunk_vertices.clear();
-			self.chunk_indices.clear();
-			self.chunk_normals.clear();
-			for _i in 0..self.num_chunks * self.num_chunks {
-				self.chunk_vertices.push(Vec::new());
-				self.chunk_indices.push(Vec::new());
-				self.chunk_normals.push(Vec::new());
-			}
+		self.paint_rules.clear();
+		if variables.contains("paint_rules") {
+			self.set_paint_rules(_owner, variables.get("paint_rules").to_array());
 		}
-		file.close();
-	}*/
+
+		if variables.contains("splat_channels") && variables.contains("splat_weights") {
+			self.load_splat_data(variables.get("splat_channels").to_i64(), variables.get("splat_weights").to_byte_array());
+		}
+
+		self.undo_stack.clear();
+		self.redo_stack.clear();
+		return true;
+	}
+
+	// The terrain arrays as they should be exported, decimated when an export error is set
+	fn export_arrays (&self) -> (Vec<Vector3>, Vec<usize>) {
+		if self.export_max_error <= 0.0 {
+			return (self.terrain_vertices.clone(), self.terrain_indices.clone());
+		}
+		let colors = self.mesher().material_ids(&self.terrain_vertices, 0, 0);
+		return decimate(&self.terrain_vertices, &self.terrain_indices, &colors, self.export_max_error);
+	}
 
 	#[export]
 	fn data_to_obj (&mut self, _owner:&Resource, path:GodotString) {
+		let (vertices, indices) = self.export_arrays();
+		let (vertices, normals, indices) = crease_normals(&vertices, &indices, self.shading_crease_angle());
 		let file:Ref<File,Unique> = File::new();
 		file.open(path, File::WRITE).unwrap();
 		file.store_line("o terrain");
-		for vertex in self.terrain_vertices.iter() {
+		for vertex in vertices.iter() {
 			file.store_line(format!("v {} {} {}", vertex.x, vertex.y, vertex.z));
 		}
-		file.store_line("s 1");
-		for i in (0..self.terrain_indices.len()).step_by(3) {
-			file.store_line(format!("f {} {} {}", self.terrain_indices[i + 2] + 1, self.terrain_indices[i + 1] + 1, self.terrain_indices[i] + 1));
+		// OBJ has a single texture coordinate channel, so only the world space UVs are written
+		if self.generate_uvs {
+			let (uvs, _) = generate_uvs(&vertices, &normals, Vector3::new(0.0, 0.0, 0.0), self.uv_scale);
+			for uv in uvs.iter() {
+				file.store_line(format!("vt {} {}", uv.x, uv.y));
+			}
+		}
+		for normal in normals.iter() {
+			file.store_line(format!("vn {} {} {}", normal.x, normal.y, normal.z));
+		}
+		for i in (0..indices.len()).step_by(3) {
+			let (a, b, c) = (indices[i + 2] + 1, indices[i + 1] + 1, indices[i] + 1);
+			if self.generate_uvs {
+				file.store_line(format!("f {}/{}/{} {}/{}/{} {}/{}/{}", a, a, a, b, b, b, c, c, c));
+			} else {
+				file.store_line(format!("f {}//{} {}//{} {}//{}", a, a, b, b, c, c));
+			}
 		}
 		file.close();
 	}
@@ -1263,25 +3116,62 @@ impl TerrainUtils {
 		if let Some(mesh_instance) = mesh_instance {
 			let mesh_instance = unsafe { mesh_instance.assume_safe() };
 			
+			let (vertices, indices) = self.export_arrays();
+			let (vertices, normals, indices) = crease_normals(&vertices, &indices, self.shading_crease_angle());
+			let (uvs, uv_classes) = if self.generate_uvs {
+				generate_uvs(&vertices, &normals, Vector3::new(0.0, 0.0, 0.0), self.uv_scale)
+			} else {
+				(Vec::new(), Vec::new())
+			};
 			let st = SurfaceTool::new();
 			st.begin(4);
-			if self.shade_smooth {
-				st.add_smooth_group(true);
-			}
 
-			for i in 0..self.terrain_vertices.len() {
-				let vert = self.terrain_vertices[i];
+			for i in 0..vertices.len() {
+				let vert = vertices[i];
 				let color:Color = self.get_vertex_color(vert.x.floor() as i32, vert.z.floor() as i32).to_color();
 				st.add_color(color);
+				st.add_normal(normals[i]);
+				if self.generate_uvs {
+					st.add_uv(uvs[i]);
+					st.add_uv2(uv_classes[i]);
+				}
 				st.add_vertex(vert);
 			}
 
-			for index in self.terrain_indices.iter() { st.add_index(*index as i64); }
-
-			st.generate_normals(false);
+			for index in indices.iter() { st.add_index(*index as i64); }
 			
-			let mesh = st.commit(GodotObject::null(), 2194432);
+			let mesh = st.commit(GodotObject::null(), self.mesh_compress_flags());
 			if let Some(mesh) = mesh { mesh_instance.set_mesh(mesh); }
 		}
 	}
 }
+
+// Maps t in [0,1] onto a ramp shape: 0 linear, 1 smoothstep, 2 ease in, 3 ease out
+fn ramp_profile (t:f32, profile:i32) -> f32 {
+	let t = t.clamp(0.0, 1.0);
+	match profile {
+		0 => t,
+		1 => t * t * (3.0 - 2.0 * t),
+		2 => t * t,
+		3 => 1.0 - (1.0 - t) * (1.0 - t),
+		_ => t
+	}
+}
+
+// Flattens a height onto the band below it. Sharpness 0 leaves the height unchanged, 1 gives hard steps
+fn terrace_height (height:i32, edges:&Vec<i32>, sharpness:f32) -> i32 {
+	for i in 1..edges.len() {
+		let lower = edges[i - 1];
+		let upper = edges[i];
+		if height >= lower && height < upper {
+			let sharpness = sharpness.clamp(0.0, 1.0);
+			if sharpness >= 1.0 {
+				return lower;
+			}
+			let t = (height - lower) as f32 / (upper - lower) as f32;
+			let shaped = t.powf(1.0 / (1.0 - sharpness));
+			return lower + ((upper - lower) as f32 * shaped).round() as i32;
+		}
+	}
+	return height;
+}
@@ -0,0 +1,26 @@
+use gdnative::prelude::{Vector2, Vector3};
+
+// Normals pointing up less steeply than this belong to walls
+const TOP_MIN_NORMAL_Y:f32 = 0.5;
+
+// World space UVs, so textures line up across chunks: tops are mapped from above and walls from the side they face, upright.
+// The second channel is the classification: x is 1 on tops and 0 on walls, y how much the vertex faces up for softer blends
+pub fn generate_uvs (vertices:&Vec<Vector3>, normals:&Vec<Vector3>, offset:Vector3, scale:f32) -> (Vec<Vector2>, Vec<Vector2>) {
+    let mut uvs:Vec<Vector2> = Vec::with_capacity(vertices.len());
+    let mut classes:Vec<Vector2> = Vec::with_capacity(vertices.len());
+    for i in 0..vertices.len() {
+        let position = vertices[i] + offset;
+        let normal = normals[i];
+        let is_top = normal.y >= TOP_MIN_NORMAL_Y;
+        let uv = if is_top {
+            Vector2::new(position.x, position.z)
+        } else if normal.x.abs() > normal.z.abs() {
+            Vector2::new(position.z, -position.y)
+        } else {
+            Vector2::new(position.x, -position.y)
+        };
+        uvs.push(uv * scale);
+        classes.push(Vector2::new(if is_top { 1.0 } else { 0.0 }, normal.y.max(0.0)));
+    }
+    return (uvs, classes);
+}